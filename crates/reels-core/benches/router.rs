@@ -0,0 +1,96 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use reels_core::handler::Service;
+use reels_core::http::{HttpRequest, HttpResponse, Method};
+use reels_core::register_service;
+use reels_core::responder::Responder;
+use reels_core::router::{PathCapture, Router, UrlPattern};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Ok;
+
+impl Service for Ok {
+    fn call(&self, _: PathCapture, _: &HttpRequest) -> HttpResponse {
+        "ok".respond()
+    }
+}
+
+register_service!(Ok);
+
+/// Routes shaped like a REST api with `resources` resources
+fn routes(resources: usize) -> Vec<(Method, String)> {
+    let mut routes = Vec::new();
+    for i in 0..resources {
+        routes.push((Method::Get, format!("/api/v1/resource{}", i)));
+        routes.push((Method::Post, format!("/api/v1/resource{}", i)));
+        routes.push((Method::Get, format!("/api/v1/resource{}/<id>", i)));
+        routes.push((Method::Delete, format!("/api/v1/resource{}/<id>", i)));
+        routes.push((
+            Method::Get,
+            format!("/api/v1/resource{}/<id>/children/<child>", i),
+        ));
+        routes.push((Method::Get, format!("/static/resource{}/<path..>", i)));
+    }
+    routes
+}
+
+fn request(method: &str, path: &str) -> HttpRequest {
+    let raw = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, path);
+    HttpRequest::parse(&mut raw.as_bytes(), None)
+        .unwrap()
+        .unwrap()
+}
+
+fn dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispatch");
+    for resources in [10, 100] {
+        let routes = routes(resources);
+        let mut router = Router::new();
+        for (method, pattern) in &routes {
+            router = router.handle(method.clone(), pattern, Ok).unwrap();
+        }
+        // How the router matched before the pattern tree, first route in registration order
+        let linear: Vec<(Method, UrlPattern)> = routes
+            .iter()
+            .map(|(method, pattern)| (method.clone(), UrlPattern::parse(pattern).unwrap()))
+            .collect();
+
+        // The last resource is the worst case for the linear scan
+        let last = resources - 1;
+        let cases = [
+            (
+                "hit",
+                "GET",
+                format!("/api/v1/resource{}/42/children/7", last),
+            ),
+            ("not_found", "GET", "/api/v2/users".to_owned()),
+            ("not_allowed", "PUT", format!("/api/v1/resource{}/42", last)),
+        ];
+        for (case, method, path) in &cases {
+            let id = format!("{}/{}", case, routes.len());
+            group.bench_with_input(BenchmarkId::new("linear", &id), path, |b, path| {
+                b.iter_batched(
+                    || request(method, path),
+                    |req| {
+                        linear.iter().position(|(method, pattern)| {
+                            *method == req.method
+                                && pattern.match_url(black_box(&req.url)).is_some()
+                        })
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+            group.bench_with_input(BenchmarkId::new("router", &id), path, |b, path| {
+                b.iter_batched(
+                    || request(method, path),
+                    |req| router.route(black_box(req)),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,532 @@
+use serde::de::{self, DeserializeOwned};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Read;
+use std::rc::Rc;
+
+use crate::http::request::RequestParseError;
+use crate::http::{HttpRequest, HttpResponse, StatusCode};
+use crate::registry::{self, Function, Registered, RegistryError};
+pub use crate::responder::Json;
+use crate::responder::Responder;
+
+/// Extract a handler argument from the incoming request
+///
+/// Handler arguments whose name matches a capture of the url pattern are taken from the
+/// path, every other argument is extracted with this trait.
+///
+/// Examples
+/// ```ignore
+/// #[post("/users/<uid>")]
+/// fn update_user(uid: u32, Json(user): Json<User>, headers: Headers) -> HttpResponse {
+///     ...
+/// }
+/// ```
+pub trait FromRequest<'a>: Sized {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection>;
+}
+
+/// The response sent back when an argument cannot be extracted from the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: StatusCode,
+    pub reason: String,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, reason: impl Into<String>) -> Self {
+        Self {
+            status,
+            reason: reason.into(),
+        }
+    }
+
+    fn bad_request(reason: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, reason)
+    }
+}
+
+impl Responder for Rejection {
+    fn respond(self) -> HttpResponse {
+        (self.status, self.reason).respond()
+    }
+}
+
+impl<'a> FromRequest<'a> for &'a HttpRequest {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        Ok(request)
+    }
+}
+
+/// The raw request body, empty if the request has none
+///
+/// Responds with 500 Internal Server Error if the server streams request bodies, take
+/// `Vec<u8>` or read `HttpRequest::body_stream` instead.
+impl<'a> FromRequest<'a> for &'a [u8] {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        if request.body.is_none() && request.body_stream.is_some() {
+            return Err(Rejection::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Request body is streamed and cannot be borrowed",
+            ));
+        }
+        Ok(request.body.as_deref().unwrap_or_default())
+    }
+}
+
+/// The raw request body, read off the connection if the server streams it
+impl<'a> FromRequest<'a> for Vec<u8> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        read_body(request).map(Cow::into_owned)
+    }
+}
+
+/// Deserialize the query string of the url
+///
+/// A key repeated like "tag=a&tag=b" or suffixed like "tag[]=a" fills a sequence, a
+/// sequence field missing from the query needs `#[serde(default)]`. Routes can require a
+/// query parameter to be present with their url pattern, like "/search?q".
+///
+/// Examples
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Search {
+///     q: String,
+///     #[serde(default)]
+///     tag: Vec<String>,
+/// }
+///
+/// #[get("/search?q")]
+/// fn search(Query(search): Query<Search>) -> String {
+///     format!("Searching {} in {:?}", search.q, search.tag)
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Query<T>(pub T);
+
+impl<'a, T: DeserializeOwned> FromRequest<'a> for Query<T> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        let query = request.url.query().unwrap_or_default();
+        reels_url_pattern::deserialize_query(query)
+            .map(Query)
+            .map_err(|e| Rejection::bad_request(format!("Invalid query string: {}", e)))
+    }
+}
+
+/// Deserialize the path captures of the route
+///
+/// Structs and maps take the captures by name, tuples take them in the order they appear in
+/// the url pattern and other types take the only capture. Responds with 400 Bad Request if
+/// a capture cannot be converted into its field.
+///
+/// Examples
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Post {
+///     user: String,
+///     id: u32,
+/// }
+///
+/// #[get("/users/<user>/posts/<id>")]
+/// fn post(Path(post): Path<Post>) -> String {
+///     format!("Post {} of {}", post.id, post.user)
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Path<T>(pub T);
+
+impl<'a, T: DeserializeOwned> FromRequest<'a> for Path<T> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        let captures = request.url_pattern.as_ref().and_then(|url_pattern| {
+            let captures = url_pattern.match_url(&request.url)?;
+            Some(url_pattern.deserialize_captures(&captures))
+        });
+        match captures {
+            Some(Ok(value)) => Ok(Path(value)),
+            Some(Err(e)) => Err(Rejection::bad_request(format!("Invalid path: {}", e))),
+            None => Err(Rejection::new(
+                StatusCode::NOT_FOUND,
+                "Request path does not match the route",
+            )),
+        }
+    }
+}
+
+/// Deserialize the host captures of the router registered with `Router::host`
+///
+/// Takes the captures like `Path<T>` does. Responds with 400 Bad Request if a capture
+/// cannot be converted into its field.
+///
+/// Examples
+/// ```ignore
+/// #[get("/")]
+/// fn dashboard(Host(tenant): Host<String>) -> String {
+///     format!("Dashboard of {}", tenant)
+/// }
+///
+/// let router = Router::new().host("<tenant>.example.com", Router::new().mount(dashboard)?)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Host<T>(pub T);
+
+impl<'a, T: DeserializeOwned> FromRequest<'a> for Host<T> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        let captures = request.host_pattern.as_ref().and_then(|host_pattern| {
+            let captures = host_pattern.match_host(request.url.host_str()?)?;
+            Some(host_pattern.deserialize_captures(&captures))
+        });
+        match captures {
+            Some(Ok(value)) => Ok(Host(value)),
+            Some(Err(e)) => Err(Rejection::bad_request(format!("Invalid host: {}", e))),
+            None => Err(Rejection::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Request was not routed by host",
+            )),
+        }
+    }
+}
+
+/// Deserialize an `application/json` request body
+impl<'a, T: DeserializeOwned> FromRequest<'a> for Json<T> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        expect_content_type(request, "application/json")?;
+        let body = read_body(request)?;
+        serde_json::from_slice(&body)
+            .map(Json)
+            .map_err(|e| Rejection::bad_request(format!("Invalid json body: {}", e)))
+    }
+}
+
+/// Deserialize an `application/x-www-form-urlencoded` request body
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Form<T>(pub T);
+
+impl<'a, T: DeserializeOwned> FromRequest<'a> for Form<T> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        expect_content_type(request, "application/x-www-form-urlencoded")?;
+        let body = read_body(request)?;
+        serde_urlencoded::from_bytes(&body)
+            .map(Form)
+            .map_err(|e| Rejection::bad_request(format!("Invalid form body: {}", e)))
+    }
+}
+
+/// The request body, read to its end off the connection if the server streams it
+///
+/// The size of a streamed body is bounded by the maximum body size of the server.
+fn read_body(request: &HttpRequest) -> Result<Cow<'_, [u8]>, Rejection> {
+    let mut stream = match (&request.body, request.body_stream()) {
+        (Some(body), _) => return Ok(Cow::Borrowed(body)),
+        (None, Some(stream)) => stream,
+        (None, None) => return Ok(Cow::Borrowed(&[])),
+    };
+    let mut body = Vec::new();
+    stream.read_to_end(&mut body).map_err(|e| {
+        let e = RequestParseError::from(e);
+        Rejection::new(e.status().unwrap_or(StatusCode::BAD_REQUEST), e.to_string())
+    })?;
+    Ok(Cow::Owned(body))
+}
+
+fn expect_content_type(request: &HttpRequest, expected: &str) -> Result<(), Rejection> {
+    let mime = request
+        .headers
+        .get("content-type")
+        .and_then(|value| value.split(';').next())
+        .map(str::trim);
+    match mime {
+        Some(mime) if mime.eq_ignore_ascii_case(expected) => Ok(()),
+        _ => Err(Rejection::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Expected content-type {}", expected),
+        )),
+    }
+}
+
+/// The application state registered with `Router::with_state`
+///
+/// Responds with 500 Internal Server Error if the router has no state or it was
+/// registered with a different type.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct State<T>(pub T);
+
+impl<'a, T: Clone + 'static> FromRequest<'a> for State<T> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        let state = request.state.as_deref().ok_or_else(|| {
+            Rejection::new(StatusCode::INTERNAL_SERVER_ERROR, "Router has no state")
+        })?;
+        state
+            .downcast_ref::<T>()
+            .cloned()
+            .map(State)
+            .ok_or_else(|| {
+                Rejection::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Router state has a different type",
+                )
+            })
+    }
+}
+
+/// Encodes the state value, which is always of the type the encoder was instantiated for
+pub(crate) type EncodeState = fn(&dyn Any) -> bincode::Result<Vec<u8>>;
+
+/// Decodes a state value encoded by the matching `EncodeState`
+pub(crate) type DecodeState = fn(&[u8]) -> bincode::Result<Rc<dyn Any>>;
+
+/// The state registered with `Router::with_state`, kept with its type
+///
+/// The value is only serialized as part of the message that spawns a connection process
+/// and decoded as soon as the process receives it, so lunatic resources like `Process`
+/// handles are transferred along with the message. It is sent as the id its type is
+/// registered under with `register_state!` and the encoded value.
+#[derive(Clone)]
+pub(crate) struct AnyState {
+    id: &'static str,
+    value: Rc<dyn Any>,
+    encode: EncodeState,
+}
+
+impl AnyState {
+    pub(crate) fn new<T>(value: T) -> Self
+    where
+        T: Serialize + DeserializeOwned + Clone + Registered + 'static,
+    {
+        Self {
+            id: T::ID,
+            value: Rc::new(value),
+            encode: encode_state::<T>,
+        }
+    }
+
+    pub(crate) fn value(&self) -> Rc<dyn Any> {
+        self.value.clone()
+    }
+}
+
+pub(crate) fn encode_state<T: Serialize + 'static>(value: &dyn Any) -> bincode::Result<Vec<u8>> {
+    let value = value
+        .downcast_ref::<T>()
+        .expect("state encoded as its own type");
+    bincode::serialize(value)
+}
+
+pub(crate) fn decode_state<T: DeserializeOwned + 'static>(
+    bytes: &[u8],
+) -> bincode::Result<Rc<dyn Any>> {
+    let value: T = bincode::deserialize(bytes)?;
+    Ok(Rc::new(value))
+}
+
+impl Serialize for AnyState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = (self.encode)(&*self.value).map_err(ser::Error::custom)?;
+        (self.id, value).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AnyState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (id, value): (String, Vec<u8>) = Deserialize::deserialize(deserializer)?;
+        match registry::lookup(&id).map_err(de::Error::custom)? {
+            (id, Function::State(encode, decode)) => Ok(Self {
+                id,
+                value: decode(&value).map_err(de::Error::custom)?,
+                encode,
+            }),
+            _ => Err(de::Error::custom(RegistryError::Unregistered(id))),
+        }
+    }
+}
+
+/// The request headers
+#[derive(Debug, Clone, Copy)]
+pub struct Headers<'a>(pub &'a HashMap<String, String>);
+
+impl<'a> Headers<'a> {
+    /// Get a header value by its case-insensitive name
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.0.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+impl<'a> FromRequest<'a> for Headers<'a> {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        Ok(Headers(&request.headers))
+    }
+}
+
+/// The cookies sent with the `cookie` header
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Cookies(pub HashMap<String, String>);
+
+impl Cookies {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+impl<'a> FromRequest<'a> for Cookies {
+    fn from_request(request: &'a HttpRequest) -> Result<Self, Rejection> {
+        let cookies = request
+            .headers
+            .get("cookie")
+            .map(|header| {
+                header
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(name, value)| {
+                        let value = value.trim();
+                        let value = value
+                            .strip_prefix('"')
+                            .and_then(|v| v.strip_suffix('"'))
+                            .unwrap_or(value);
+                        (name.trim().to_owned(), value.to_owned())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Cookies(cookies))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Method;
+    use crate::register_state;
+    use lunatic::{Mailbox, Process};
+    use serde::Deserialize;
+    use std::io;
+
+    register_state!(Process<u32>);
+
+    fn request(headers: &[(&str, &str)], body: &str) -> HttpRequest {
+        let mut request = HttpRequest::test(Method::Post, "/");
+        request.headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        request.body = Some(body.as_bytes().to_vec());
+        request
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        name: String,
+    }
+
+    #[test]
+    fn json_body() {
+        let req = request(
+            &[("content-type", "application/json; charset=utf-8")],
+            r#"{"name": "reels"}"#,
+        );
+        let Json(user) = Json::<User>::from_request(&req).unwrap();
+        assert_eq!(user.name, "reels");
+
+        let req = request(&[("content-type", "text/plain")], r#"{"name": "reels"}"#);
+        let rejection = Json::<User>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(rejection.reason, "Expected content-type application/json");
+        let req = request(&[], r#"{"name": "reels"}"#);
+        let rejection = Json::<User>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let req = request(&[("content-type", "application/json")], r#"{"name": "#);
+        let rejection = Json::<User>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::BAD_REQUEST);
+        assert!(rejection.reason.starts_with("Invalid json body: "));
+    }
+
+    #[test]
+    fn form_body() {
+        let content_type = ("content-type", "application/x-www-form-urlencoded");
+        let req = request(&[content_type], "name=reels+web");
+        let Form(user) = Form::<User>::from_request(&req).unwrap();
+        assert_eq!(user.name, "reels web");
+
+        let req = request(&[("content-type", "application/json")], "name=reels");
+        let rejection = Form::<User>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let req = request(&[content_type], "title=reels");
+        let rejection = Form::<User>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn streamed_bodies() {
+        use crate::http::body::Framing;
+
+        let streamed = |content_type: &str, body: &str| {
+            let mut req = request(&[("content-type", content_type)], "");
+            req.body = None;
+            let reader = Box::new(io::Cursor::new(body.as_bytes().to_vec()));
+            req.stream_body(reader, Framing::Length(body.len() as u64), None);
+            req
+        };
+
+        let req = streamed("application/json", r#"{"name": "reels"}"#);
+        let Json(user) = Json::<User>::from_request(&req).unwrap();
+        assert_eq!(user.name, "reels");
+        let req = streamed("application/x-www-form-urlencoded", "name=reels");
+        let Form(user) = Form::<User>::from_request(&req).unwrap();
+        assert_eq!(user.name, "reels");
+        let req = streamed("text/plain", "reels");
+        assert_eq!(Vec::<u8>::from_request(&req).unwrap(), b"reels");
+
+        let req = streamed("text/plain", "reels");
+        let rejection = <&[u8]>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::INTERNAL_SERVER_ERROR);
+        // The length of a chunked body is only known once it is read
+        let mut req = streamed("text/plain", "");
+        let reader = Box::new(io::Cursor::new(b"5\r\nreels\r\n0\r\n\r\n".to_vec()));
+        req.stream_body(reader, Framing::Chunked, Some(4));
+        let rejection = Vec::<u8>::from_request(&req).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn headers_are_case_insensitive() {
+        let req = request(&[("x-request-id", "7")], "");
+        let headers = Headers::from_request(&req).unwrap();
+        assert_eq!(headers.get("X-Request-Id"), Some("7"));
+        assert_eq!(headers.get("x-request-id"), Some("7"));
+        assert_eq!(headers.get("x-trace-id"), None);
+    }
+
+    #[test]
+    fn cookies() {
+        let req = request(
+            &[(
+                "cookie",
+                r#"session=abc; theme="dark mode" ; flag; lang=en=US"#,
+            )],
+            "",
+        );
+        let cookies = Cookies::from_request(&req).unwrap();
+        assert_eq!(cookies.get("session"), Some("abc"));
+        assert_eq!(cookies.get("theme"), Some("dark mode"));
+        assert_eq!(cookies.get("flag"), None);
+        assert_eq!(cookies.get("lang"), Some("en=US"));
+        assert_eq!(cookies.0.len(), 3);
+
+        let cookies = Cookies::from_request(&request(&[], "")).unwrap();
+        assert!(cookies.0.is_empty());
+    }
+
+    #[lunatic::test]
+    fn process_handles_round_trip_through_the_state(mailbox: Mailbox<u32>) {
+        // The state is decoded in the spawned process, which takes over the handle
+        let state = AnyState::new(mailbox.this());
+        Process::spawn(state, |state, _: Mailbox<()>| {
+            let value = state.value();
+            let parent = value.downcast_ref::<Process<u32>>().unwrap();
+            parent.send(42);
+        });
+        assert_eq!(mailbox.receive(), 42);
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{de, de::DeserializeOwned, ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::rc::Rc;
+
+use crate::http::{HttpRequest, HttpResponse};
+use crate::registry::{self, Function, Registered, RegisteredFn, RegistryError};
+use crate::router::{AcceptsFunc, HandlerFunc, PathCapture, SegmentTypeMissmatch};
+
+/// A handler carrying serializable configuration
+///
+/// The handler is sent into every connection process along with the router and decoded
+/// there by the id it is registered under with `register_service!`.
+///
+/// Examples
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Greet {
+///     greeting: String,
+/// }
+///
+/// impl Service for Greet {
+///     fn call(&self, _: PathCapture, _: &HttpRequest) -> HttpResponse {
+///         format!("{} world!", self.greeting).respond()
+///     }
+/// }
+///
+/// register_service!(Greet);
+///
+/// let greet = Greet { greeting: "Hello".to_owned() };
+/// let router = Router::new().handle(Method::Get, "/", greet)?;
+/// ```
+pub trait Service: Serialize + DeserializeOwned + 'static {
+    fn call(&self, path_capture: PathCapture, request: &HttpRequest) -> HttpResponse;
+
+    /// Whether the service handles a path with these captures, the request falls through
+    /// to the next matching route otherwise
+    fn accepts(&self, _path_capture: &PathCapture) -> bool {
+        true
+    }
+}
+
+/// A `Service` with its type erased
+pub(crate) trait ErasedService {
+    fn call(&self, path_capture: PathCapture, request: &HttpRequest) -> HttpResponse;
+    fn accepts(&self, path_capture: &PathCapture) -> bool;
+    fn encode(&self) -> bincode::Result<Vec<u8>>;
+}
+
+impl<S: Service> ErasedService for S {
+    fn call(&self, path_capture: PathCapture, request: &HttpRequest) -> HttpResponse {
+        Service::call(self, path_capture, request)
+    }
+
+    fn accepts(&self, path_capture: &PathCapture) -> bool {
+        Service::accepts(self, path_capture)
+    }
+
+    fn encode(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+}
+
+/// Decodes a registered `Service` from its bincode encoding
+pub(crate) type DecodeService = fn(&[u8]) -> bincode::Result<Rc<dyn ErasedService>>;
+
+pub(crate) fn decode_service<S: Service>(bytes: &[u8]) -> bincode::Result<Rc<dyn ErasedService>> {
+    let service: S = bincode::deserialize(bytes)?;
+    Ok(Rc::new(service))
+}
+
+/// A type erased handler that can be sent into another process
+///
+/// It is serialized as the id of its registration along with the encoded service, and
+/// decoded by looking the id up in the registry of the receiving process.
+#[derive(Clone)]
+pub struct BoxedHandler(Inner);
+
+#[derive(Clone)]
+enum Inner {
+    Fn(RegisteredFn<(HandlerFunc, AcceptsFunc)>),
+    Service(&'static str, Rc<dyn ErasedService>),
+}
+
+impl BoxedHandler {
+    /// Wrap a handler function registered with `handler_fn!`
+    pub fn from_fn(handler: RegisteredFn<(HandlerFunc, AcceptsFunc)>) -> Self {
+        Self(Inner::Fn(handler))
+    }
+
+    /// Wrap a handler carrying serializable configuration
+    pub fn service<S: Service + Registered>(service: S) -> Self {
+        Self(Inner::Service(S::ID, Rc::new(service)))
+    }
+
+    /// The id the handler is registered under
+    pub fn id(&self) -> &'static str {
+        match &self.0 {
+            Inner::Fn(handler) => handler.id(),
+            Inner::Service(id, _) => id,
+        }
+    }
+
+    /// Whether the handler handles a path with these captures
+    pub fn accepts(&self, path_capture: &PathCapture) -> bool {
+        match &self.0 {
+            Inner::Fn(handler) => handler.get().1(path_capture),
+            Inner::Service(_, service) => service.accepts(path_capture),
+        }
+    }
+
+    pub fn call(
+        &self,
+        path_capture: PathCapture,
+        request: &HttpRequest,
+    ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+        match &self.0 {
+            Inner::Fn(handler) => handler.get().0(path_capture, request),
+            Inner::Service(_, service) => Ok(service.call(path_capture, request)),
+        }
+    }
+}
+
+impl Serialize for BoxedHandler {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let service = match &self.0 {
+            Inner::Fn(_) => Vec::new(),
+            Inner::Service(_, service) => service.encode().map_err(ser::Error::custom)?,
+        };
+        (self.id(), service).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BoxedHandler {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (id, service): (String, Vec<u8>) = Deserialize::deserialize(deserializer)?;
+        match registry::lookup(&id).map_err(de::Error::custom)? {
+            (id, Function::Handler(handler)) => Ok(Self::from_fn(RegisteredFn::new(id, handler))),
+            (id, Function::Service(decode)) => {
+                let service = decode(&service).map_err(de::Error::custom)?;
+                Ok(Self(Inner::Service(id, service)))
+            }
+            _ => Err(de::Error::custom(RegistryError::Unregistered(id))),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use super::request::RequestParseError;
+
+/// Maximum length of a chunk size line, extensions included
+const MAX_CHUNK_LINE_LENGTH: usize = 4 * 1024;
+/// Maximum length of the trailer section of a chunked body
+const MAX_TRAILERS_LENGTH: usize = 16 * 1024;
+
+/// How the end of a request body is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// The request has no body
+    Empty,
+    /// The body is `Content-Length` bytes long
+    Length(u64),
+    /// The body is sent in chunks with `Transfer-Encoding: chunked`
+    Chunked,
+}
+
+/// Position of the decoder in the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Bytes left of a body with a known length
+    Length(u64),
+    /// Expecting the size line of the next chunk
+    ChunkSize,
+    /// Bytes left of the current chunk
+    Chunk(u64),
+    /// Expecting the line break after the data of a chunk
+    ChunkEnd,
+    /// Reading the trailer fields after the last chunk
+    Trailers,
+    Done,
+}
+
+/// Reads a request body off the connection without reading past its end
+///
+/// Chunked bodies are decoded, chunk extensions are ignored and trailer fields are
+/// collected into `trailers`.
+pub(crate) struct BodyDecoder<R> {
+    reader: R,
+    state: State,
+    /// Maximum number of body bytes, `None` for unlimited
+    max_size: Option<u64>,
+    /// Number of body bytes announced so far
+    size: u64,
+    trailers_length: usize,
+    pub(crate) trailers: HashMap<String, String>,
+}
+
+impl<R: Read> BodyDecoder<R> {
+    pub(crate) fn new(reader: R, framing: Framing, max_size: Option<usize>) -> Self {
+        let state = match framing {
+            Framing::Empty => State::Done,
+            Framing::Length(len) => State::Length(len),
+            Framing::Chunked => State::ChunkSize,
+        };
+        Self {
+            reader,
+            state,
+            max_size: max_size.map(|max| max as u64),
+            size: 0,
+            trailers_length: 0,
+            trailers: HashMap::new(),
+        }
+    }
+
+    /// Read and discard the rest of the body
+    pub(crate) fn discard(&mut self) -> io::Result<()> {
+        io::copy(self, &mut io::sink()).map(|_| ())
+    }
+
+    /// Read a line ending with CRLF, without the line break
+    fn read_line(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        let mut byte = [0];
+        loop {
+            self.reader.read_exact(&mut byte)?;
+            if byte[0] == b'\n' {
+                return match line.pop() {
+                    Some(b'\r') => Ok(line),
+                    _ => Err(invalid_chunk()),
+                };
+            }
+            if line.len() > max {
+                return Err(invalid_chunk());
+            }
+            line.push(byte[0]);
+        }
+    }
+
+    fn read_chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line(MAX_CHUNK_LINE_LENGTH)?;
+        // Chunk extensions after `;` carry nothing the server understands
+        let size = line.split(|b| *b == b';').next().unwrap_or_default();
+        let size = match size.iter().rposition(|b| *b != b' ' && *b != b'\t') {
+            Some(end) => &size[..=end],
+            None => return Err(invalid_chunk()),
+        };
+        if size.len() > 16 || !size.iter().all(u8::is_ascii_hexdigit) {
+            return Err(invalid_chunk());
+        }
+        let size = std::str::from_utf8(size).map_err(|_| invalid_chunk())?;
+        u64::from_str_radix(size, 16).map_err(|_| invalid_chunk())
+    }
+
+    fn read_trailer(&mut self) -> io::Result<Option<(String, String)>> {
+        let line = self.read_line(MAX_TRAILERS_LENGTH.saturating_sub(self.trailers_length))?;
+        self.trailers_length += line.len() + 2;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let line = std::str::from_utf8(&line).map_err(|_| invalid_chunk())?;
+        match line.split_once(':') {
+            Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                Ok(Some((name.to_ascii_lowercase(), value.trim().to_owned())))
+            }
+            _ => Err(invalid_chunk()),
+        }
+    }
+}
+
+impl<R: Read> Read for BodyDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.state {
+                State::Done | State::Length(0) => return Ok(0),
+                State::Length(remaining) | State::Chunk(remaining) => {
+                    let max = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+                    let n = self.reader.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    let remaining = remaining - n as u64;
+                    self.state = match self.state {
+                        State::Length(_) => State::Length(remaining),
+                        _ if remaining == 0 => State::ChunkEnd,
+                        _ => State::Chunk(remaining),
+                    };
+                    return Ok(n);
+                }
+                State::ChunkSize => {
+                    let size = self.read_chunk_size()?;
+                    self.size = self.size.saturating_add(size);
+                    if self.max_size.is_some_and(|max| self.size > max) {
+                        return Err(body_error(RequestParseError::PayloadTooLarge));
+                    }
+                    self.state = match size {
+                        0 => State::Trailers,
+                        size => State::Chunk(size),
+                    };
+                }
+                State::ChunkEnd => {
+                    if !self.read_line(0)?.is_empty() {
+                        return Err(invalid_chunk());
+                    }
+                    self.state = State::ChunkSize;
+                }
+                State::Trailers => match self.read_trailer()? {
+                    Some((name, value)) => {
+                        self.trailers.insert(name, value);
+                    }
+                    None => self.state = State::Done,
+                },
+            }
+        }
+    }
+}
+
+/// Carry a request error through `io::Read`, `RequestParseError::from` unwraps it again
+fn body_error(e: RequestParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn invalid_chunk() -> io::Error {
+    body_error(RequestParseError::InvalidChunk)
+}
+
+/// The body of a request left on the connection for the handler to read
+///
+/// Clones share the same position in the body. Whatever the handler does not read is
+/// discarded before the next request on the connection.
+#[derive(Clone)]
+pub struct BodyStream {
+    decoder: Rc<RefCell<BodyDecoder<Box<dyn Read>>>>,
+}
+
+impl BodyStream {
+    pub(crate) fn new(reader: Box<dyn Read>, framing: Framing, max_size: Option<usize>) -> Self {
+        Self {
+            decoder: Rc::new(RefCell::new(BodyDecoder::new(reader, framing, max_size))),
+        }
+    }
+
+    /// The trailer fields of a chunked body, available once the body was read to its end
+    pub fn trailers(&self) -> HashMap<String, String> {
+        self.decoder.borrow().trailers.clone()
+    }
+
+    /// Read and discard the rest of the body
+    pub(crate) fn discard(&self) -> io::Result<()> {
+        self.decoder.borrow_mut().discard()
+    }
+}
+
+impl Read for BodyStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.decoder.borrow_mut().read(buf)
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyStream")
+            .field("state", &self.decoder.borrow().state)
+            .finish()
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Method {
@@ -22,12 +23,32 @@ impl Method {
             "POST" => Ok(Method::Post),
             "PATCH" => Ok(Method::Patch),
             "TRACE" => Ok(Method::Trace),
-            "Delete" => Ok(Method::Delete),
+            "DELETE" => Ok(Method::Delete),
             "CONNECT" => Ok(Method::Connect),
             "OPTIONS" => Ok(Method::Options),
             _ => Err(()),
         }
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Put => "PUT",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Patch => "PATCH",
+            Method::Trace => "TRACE",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl TryInto<Method> for &str {
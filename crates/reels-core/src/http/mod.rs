@@ -1,11 +1,13 @@
+pub mod body;
 pub mod method;
 pub mod request;
 pub mod response;
 pub mod status;
 pub mod version;
 
+pub use body::BodyStream;
 pub use method::Method;
 pub use request::HttpRequest;
-pub use response::{HttpResponse, HttpResponseBuilder};
+pub use response::{Body, HttpResponse, HttpResponseBuilder};
 pub use status::StatusCode;
 pub use version::Version;
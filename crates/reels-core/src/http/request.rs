@@ -1,12 +1,21 @@
 use httparse::Request;
-use lunatic::net;
+use reels_url_pattern::{HostPattern, UrlPattern};
+use std::any::Any;
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{self, BufRead, Read};
+use std::rc::Rc;
+use std::{error, fmt};
 use url::Url;
 
-use super::{Method, Version};
+use super::body::{BodyDecoder, BodyStream, Framing};
+use super::{Method, StatusCode, Version};
 
-const MAX_HEADER_LENGTH: usize = 32;
+/// Maximum number of headers in a request
+const MAX_HEADERS: usize = 32;
+/// Maximum length of the request line in bytes
+const MAX_REQUEST_LINE_LENGTH: usize = 8 * 1024;
+/// Maximum length of the request line and headers in bytes
+const MAX_HEAD_LENGTH: usize = 16 * 1024;
 
 #[derive(Debug)]
 pub struct HttpRequest {
@@ -18,77 +27,631 @@ pub struct HttpRequest {
     pub version: Version,
     /// The request's headers
     pub headers: HashMap<String, String>,
-    /// HTTP body
+    /// HTTP body, `None` if the request has none or the server streams it
     pub body: Option<Vec<u8>>,
+    /// The trailer fields of a buffered chunked body
+    pub trailers: HashMap<String, String>,
+    /// The body left on the connection when the server streams request bodies
+    pub(crate) body_stream: Option<BodyStream>,
+    /// The state of the router handling the request
+    pub(crate) state: Option<Rc<dyn Any>>,
+    /// The url pattern of the route handling the request
+    pub(crate) url_pattern: Option<UrlPattern>,
+    /// The host pattern of the router handling the request
+    pub(crate) host_pattern: Option<HostPattern>,
 }
 
+#[cfg(test)]
+impl HttpRequest {
+    /// A request for the path on localhost without headers or body, for tests
+    pub(crate) fn test(method: Method, path: &str) -> Self {
+        Self {
+            method,
+            url: Url::parse(&format!("http://localhost{}", path)).unwrap(),
+            version: Version::HTTP_11,
+            headers: HashMap::new(),
+            body: None,
+            body_stream: None,
+            trailers: HashMap::new(),
+            state: None,
+            url_pattern: None,
+            host_pattern: None,
+        }
+    }
+}
+
+/// Error reading a request from a connection
 #[derive(Debug)]
 pub enum RequestParseError {
+    /// The connection was closed in the middle of a request
     SocketClosed,
-    InvalidUrl,
+    /// Reading from the connection failed or timed out
+    Io(io::Error),
+    /// The request line is longer than the server accepts
+    UriTooLong,
+    /// The request line and headers are longer than the server accepts
+    HeaderTooLarge,
+    /// The request has more headers than the server accepts
+    TooManyHeaders,
+    /// The request method is not supported
     InvalidMethod,
+    /// The request is not HTTP/1.0 or HTTP/1.1
+    UnsupportedVersion,
+    /// A header value is not valid UTF-8
+    InvalidHeaderValue,
+    /// An HTTP/1.1 request without a single valid host header
+    InvalidHost,
+    /// The request target and host do not form a valid url
+    InvalidUrl,
+    /// The `Content-Length` header is repeated or not a number
+    InvalidContentLength,
+    /// The body is longer than the server accepts
+    PayloadTooLarge,
+    /// The `Transfer-Encoding` header does not end with `chunked` or comes with a
+    /// `Content-Length`
+    InvalidTransferEncoding,
+    /// The body is compressed with a transfer coding the server cannot decode
+    UnsupportedTransferEncoding,
+    /// A chunk of a chunked body is malformed
+    InvalidChunk,
+    /// The request is not valid HTTP
     InvalidHttpRequest,
 }
 
+impl RequestParseError {
+    /// The status of the response sent before closing the connection, `None` if the
+    /// client cannot receive one
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            RequestParseError::SocketClosed => None,
+            RequestParseError::Io(e) if e.kind() == io::ErrorKind::TimedOut => {
+                Some(StatusCode::REQUEST_TIMEOUT)
+            }
+            RequestParseError::Io(_) => None,
+            RequestParseError::UriTooLong => Some(StatusCode::URI_TOO_LONG),
+            RequestParseError::PayloadTooLarge => Some(StatusCode::PAYLOAD_TOO_LARGE),
+            RequestParseError::HeaderTooLarge | RequestParseError::TooManyHeaders => {
+                Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+            }
+            RequestParseError::InvalidMethod | RequestParseError::UnsupportedTransferEncoding => {
+                Some(StatusCode::NOT_IMPLEMENTED)
+            }
+            RequestParseError::UnsupportedVersion => Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+            RequestParseError::InvalidHeaderValue
+            | RequestParseError::InvalidHost
+            | RequestParseError::InvalidUrl
+            | RequestParseError::InvalidContentLength
+            | RequestParseError::InvalidTransferEncoding
+            | RequestParseError::InvalidChunk
+            | RequestParseError::InvalidHttpRequest => Some(StatusCode::BAD_REQUEST),
+        }
+    }
+}
+
+impl fmt::Display for RequestParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestParseError::SocketClosed => write!(f, "Connection closed during the request"),
+            RequestParseError::Io(e) => write!(f, "Failed to read the request: {}", e),
+            RequestParseError::UriTooLong => write!(
+                f,
+                "Request line is longer than {} bytes",
+                MAX_REQUEST_LINE_LENGTH
+            ),
+            RequestParseError::HeaderTooLarge => {
+                write!(
+                    f,
+                    "Request headers are longer than {} bytes",
+                    MAX_HEAD_LENGTH
+                )
+            }
+            RequestParseError::TooManyHeaders => {
+                write!(f, "Request has more than {} headers", MAX_HEADERS)
+            }
+            RequestParseError::InvalidMethod => write!(f, "Request method is not supported"),
+            RequestParseError::UnsupportedVersion => write!(f, "HTTP version is not supported"),
+            RequestParseError::InvalidHeaderValue => {
+                write!(f, "Header value is not valid UTF-8")
+            }
+            RequestParseError::InvalidHost => {
+                write!(f, "Request must have a single valid host header")
+            }
+            RequestParseError::InvalidUrl => write!(f, "Request target is not a valid url"),
+            RequestParseError::InvalidContentLength => {
+                write!(f, "Request must have a single valid content-length header")
+            }
+            RequestParseError::PayloadTooLarge => {
+                write!(f, "Request body is larger than the server accepts")
+            }
+            RequestParseError::InvalidTransferEncoding => {
+                write!(f, "Request body length cannot be determined")
+            }
+            RequestParseError::UnsupportedTransferEncoding => {
+                write!(f, "Transfer coding is not supported")
+            }
+            RequestParseError::InvalidChunk => write!(f, "Malformed chunked request body"),
+            RequestParseError::InvalidHttpRequest => write!(f, "Malformed HTTP request"),
+        }
+    }
+}
+
+impl error::Error for RequestParseError {}
+
+impl From<io::Error> for RequestParseError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            return RequestParseError::SocketClosed;
+        }
+        // Errors of the body decoder are carried inside io errors
+        if e.get_ref()
+            .is_some_and(|inner| inner.is::<RequestParseError>())
+        {
+            let inner = e.into_inner().expect("io error has an inner error");
+            return *inner
+                .downcast::<RequestParseError>()
+                .expect("inner error is a RequestParseError");
+        }
+        RequestParseError::Io(e)
+    }
+}
+
 impl HttpRequest {
-    pub fn parse(
-        buf_reader: &mut BufReader<net::TcpStream>,
+    /// Read the next request and its body from the connection, `None` if it was closed
+    /// before one started
+    ///
+    /// Bodies longer than `max_body_size` are rejected without reading them.
+    pub fn parse<R: BufRead>(
+        buf_reader: &mut R,
+        max_body_size: Option<usize>,
     ) -> Result<Option<Self>, RequestParseError> {
-        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADER_LENGTH];
+        let (mut request, framing) = match Self::parse_head(buf_reader, max_body_size)? {
+            Some(parsed) => parsed,
+            None => return Ok(None),
+        };
+        request.read_body(buf_reader, framing, max_body_size)?;
+        Ok(Some(request))
+    }
+
+    /// The body of the request when the server leaves it on the connection
+    ///
+    /// Examples
+    /// ```ignore
+    /// #[post("/upload")]
+    /// fn upload(request: &HttpRequest) -> String {
+    ///     let mut body = request.body_stream().expect("request bodies are streamed");
+    ///     match std::io::copy(&mut body, &mut std::io::sink()) {
+    ///         Ok(size) => format!("Received {} bytes", size),
+    ///         Err(e) => format!("Upload failed: {}", e),
+    ///     }
+    /// }
+    /// ```
+    pub fn body_stream(&self) -> Option<BodyStream> {
+        self.body_stream.clone()
+    }
+
+    /// Read the request line and headers, leaving the body on the connection
+    pub(crate) fn parse_head<R: BufRead>(
+        buf_reader: &mut R,
+        max_body_size: Option<usize>,
+    ) -> Result<Option<(Self, Framing)>, RequestParseError> {
+        let head = match read_head(buf_reader)? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
         let mut req = Request::new(&mut headers);
+        match req.parse(&head) {
+            Ok(httparse::Status::Complete(_)) => {}
+            Ok(httparse::Status::Partial) => return Err(RequestParseError::InvalidHttpRequest),
+            Err(httparse::Error::TooManyHeaders) => return Err(RequestParseError::TooManyHeaders),
+            Err(httparse::Error::Version) => return Err(RequestParseError::UnsupportedVersion),
+            Err(_) => return Err(RequestParseError::InvalidHttpRequest),
+        }
 
-        let mut header_buffer = Vec::new();
-
-        loop {
-            match buf_reader.read_until(b'\n', &mut header_buffer) {
-                Ok(n) if n == 0 => return Ok(None),
-                Ok(_) => {
-                    let n = header_buffer.len();
-                    if n >= 3 && header_buffer[n - 3] == b'\n' {
-                        break;
-                    }
-                }
-                Err(_) => return Err(RequestParseError::InvalidHttpRequest),
+        let method: Method = req
+            .method
+            .ok_or(RequestParseError::InvalidHttpRequest)?
+            .try_into()
+            .map_err(|_| RequestParseError::InvalidMethod)?;
+        let version: Version = req
+            .version
+            .ok_or(RequestParseError::InvalidHttpRequest)?
+            .try_into()
+            .map_err(|_| RequestParseError::UnsupportedVersion)?;
+        let mut headers: HashMap<String, String> = HashMap::new();
+        let mut hosts = 0;
+        let mut content_lengths = 0;
+        let mut transfer_codings = Vec::new();
+        for header in req.headers.iter() {
+            let name = header.name.to_ascii_lowercase();
+            let value = std::str::from_utf8(header.value)
+                .map_err(|_| RequestParseError::InvalidHeaderValue)?;
+            match name.as_str() {
+                "host" => hosts += 1,
+                "content-length" => content_lengths += 1,
+                "transfer-encoding" => transfer_codings.extend(
+                    value
+                        .split(',')
+                        .map(|coding| coding.trim().to_ascii_lowercase()),
+                ),
+                _ => {}
             }
+            headers.insert(name, value.to_owned());
         }
 
-        req.parse(&header_buffer).unwrap();
+        let path = req.path.ok_or(RequestParseError::InvalidHttpRequest)?;
+        let url = parse_url(path, &headers, version, hosts)?;
+        let framing = parse_framing(&headers, content_lengths, &transfer_codings, version)?;
+        if let (Framing::Length(len), Some(max)) = (framing, max_body_size) {
+            if len > max as u64 {
+                return Err(RequestParseError::PayloadTooLarge);
+            }
+        }
 
-        let method: Method = req.method.unwrap().try_into().unwrap();
-        let version: Version = req.version.unwrap().try_into().unwrap();
-        let headers: HashMap<String, String> = req
-            .headers
-            .iter_mut()
-            .map(|h| {
-                (
-                    h.name.to_ascii_lowercase().to_owned(),
-                    std::str::from_utf8(h.value).unwrap().to_owned(),
-                )
-            })
-            .collect();
-
-        let host = headers.get("host").unwrap();
-        let path = req.path.unwrap();
-        // Parse url string into type URL and only take the path portion
-        let url: Url = Url::parse(&format!("http://{}{}", host, path)).unwrap();
-
-        let content_length = headers
-            .get("content-length")
-            .and_then(|s| s.parse::<usize>().ok());
-
-        let body = content_length.map(|len| {
-            let mut body_buf = Vec::new();
-            body_buf.reserve_exact(len);
-            buf_reader.read_exact(&mut body_buf).unwrap();
-            body_buf
-        });
-
-        Ok(Some(Self {
+        let request = Self {
             method,
             url,
-            body,
+            body: None,
+            body_stream: None,
+            trailers: HashMap::new(),
             version,
             headers,
-        }))
+            state: None,
+            url_pattern: None,
+            host_pattern: None,
+        };
+        Ok(Some((request, framing)))
+    }
+
+    /// Read the whole body into `body` and its trailers into `trailers`
+    pub(crate) fn read_body<R: Read>(
+        &mut self,
+        reader: R,
+        framing: Framing,
+        max_body_size: Option<usize>,
+    ) -> Result<(), RequestParseError> {
+        if framing == Framing::Empty {
+            return Ok(());
+        }
+        let mut body = Vec::new();
+        let mut decoder = BodyDecoder::new(reader, framing, max_body_size);
+        decoder.read_to_end(&mut body)?;
+        self.body = Some(body);
+        self.trailers = decoder.trailers;
+        Ok(())
+    }
+
+    /// Leave the body on the connection for the handler to read through `body_stream`
+    pub(crate) fn stream_body(
+        &mut self,
+        reader: Box<dyn Read>,
+        framing: Framing,
+        max_body_size: Option<usize>,
+    ) -> BodyStream {
+        let stream = BodyStream::new(reader, framing, max_body_size);
+        self.body_stream = Some(stream.clone());
+        stream
+    }
+}
+
+/// Read the request line and headers up to the empty line ending them
+fn read_head<R: BufRead>(buf_reader: &mut R) -> Result<Option<Vec<u8>>, RequestParseError> {
+    let mut head = Vec::new();
+    loop {
+        let line_start = head.len();
+        // Read one byte past the limit to tell a line ending at the limit from a longer one
+        let limit = if line_start == 0 {
+            MAX_REQUEST_LINE_LENGTH
+        } else {
+            MAX_HEAD_LENGTH - line_start
+        };
+        let read = buf_reader
+            .by_ref()
+            .take(limit as u64 + 1)
+            .read_until(b'\n', &mut head)?;
+        if read == 0 {
+            if head.is_empty() {
+                return Ok(None);
+            }
+            return Err(RequestParseError::SocketClosed);
+        }
+        if line_start == 0 && head.len() > MAX_REQUEST_LINE_LENGTH {
+            return Err(RequestParseError::UriTooLong);
+        }
+        if head.len() > MAX_HEAD_LENGTH {
+            return Err(RequestParseError::HeaderTooLarge);
+        }
+        if line_start == 0 && (head == b"\r\n" || head == b"\n") {
+            // Empty lines before a request are ignored
+            head.clear();
+        } else if head.ends_with(b"\n\r\n") || head.ends_with(b"\n\n") {
+            return Ok(Some(head));
+        }
+    }
+}
+
+/// Find how the body of the request is delimited
+fn parse_framing(
+    headers: &HashMap<String, String>,
+    content_lengths: usize,
+    transfer_codings: &[String],
+    version: Version,
+) -> Result<Framing, RequestParseError> {
+    if !transfer_codings.is_empty() {
+        // Both headers at once are how requests get smuggled past proxies
+        if content_lengths > 0 || version == Version::HTTP_10 {
+            return Err(RequestParseError::InvalidTransferEncoding);
+        }
+        return match transfer_codings.split_last() {
+            Some((last, [])) if last == "chunked" => Ok(Framing::Chunked),
+            Some((last, _)) if last == "chunked" => {
+                Err(RequestParseError::UnsupportedTransferEncoding)
+            }
+            _ => Err(RequestParseError::InvalidTransferEncoding),
+        };
+    }
+    let content_length = match headers.get("content-length") {
+        Some(value) if content_lengths == 1 => value.trim(),
+        Some(_) => return Err(RequestParseError::InvalidContentLength),
+        None => return Ok(Framing::Empty),
+    };
+    // `u64::from_str` accepts a leading `+`, which is not a valid length
+    if content_length.is_empty() || !content_length.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(RequestParseError::InvalidContentLength);
+    }
+    content_length
+        .parse()
+        .map(Framing::Length)
+        .map_err(|_| RequestParseError::InvalidContentLength)
+}
+
+/// Build the url of the request from its target and host header
+fn parse_url(
+    path: &str,
+    headers: &HashMap<String, String>,
+    version: Version,
+    hosts: usize,
+) -> Result<Url, RequestParseError> {
+    if !path.starts_with('/') {
+        // Absolute form used when talking to proxies
+        return match Url::parse(path) {
+            Ok(url) if url.has_host() => Ok(url),
+            _ => Err(RequestParseError::InvalidUrl),
+        };
+    }
+    let host = match headers.get("host") {
+        Some(host) if hosts == 1 => host.as_str(),
+        None if version == Version::HTTP_10 => "localhost",
+        _ => return Err(RequestParseError::InvalidHost),
+    };
+    if host.is_empty() || host.contains(|c: char| "/?#@\\".contains(c) || c.is_whitespace()) {
+        return Err(RequestParseError::InvalidHost);
+    }
+    Url::parse(&format!("http://{}{}", host, path)).map_err(|_| RequestParseError::InvalidUrl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &[u8]) -> Result<Option<HttpRequest>, RequestParseError> {
+        HttpRequest::parse(&mut &raw[..], Some(16))
+    }
+
+    fn status(raw: &[u8]) -> Option<StatusCode> {
+        parse(raw).unwrap_err().status()
+    }
+
+    #[test]
+    fn parse_requests() {
+        let request =
+            parse(b"\r\nGET /users?page=2 HTTP/1.1\r\nHost: example.com\r\nX-Id: 7\r\n\r\n")
+                .unwrap()
+                .unwrap();
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.version, Version::HTTP_11);
+        assert_eq!(request.url.as_str(), "http://example.com/users?page=2");
+        assert_eq!(request.headers["x-id"], "7");
+
+        let request = parse(b"GET / HTTP/1.0\r\n\r\n").unwrap().unwrap();
+        assert_eq!(request.url.as_str(), "http://localhost/");
+
+        let request = parse(b"GET http://example.com/a HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.url.path(), "/a");
+
+        assert!(parse(b"").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_errors_map_to_status() {
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost: exa"),
+            Err(RequestParseError::SocketClosed)
+        ));
+        assert_eq!(status(b"GET / HTTP/1.1\r\nHost: exa"), None);
+
+        let long_path = format!(
+            "GET /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_REQUEST_LINE_LENGTH)
+        );
+        assert_eq!(status(long_path.as_bytes()), Some(StatusCode::URI_TOO_LONG));
+
+        let long_header = format!(
+            "GET / HTTP/1.1\r\nHost: a\r\nX-Long: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD_LENGTH)
+        );
+        assert_eq!(
+            status(long_header.as_bytes()),
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+
+        let many_headers = format!(
+            "GET / HTTP/1.1\r\nHost: a\r\n{}\r\n",
+            "X-A: a\r\n".repeat(MAX_HEADERS)
+        );
+        assert_eq!(
+            status(many_headers.as_bytes()),
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+
+        assert_eq!(
+            status(b"GET / HTTP/2.0\r\nHost: a\r\n\r\n"),
+            Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED)
+        );
+        assert_eq!(
+            status(b"BREW / HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Some(StatusCode::NOT_IMPLEMENTED)
+        );
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nHost: a\r\nX-A: \xff\r\n\r\n"),
+            Some(StatusCode::BAD_REQUEST)
+        );
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\n\r\n"),
+            Err(RequestParseError::InvalidHost)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n"),
+            Err(RequestParseError::InvalidHost)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost: a.com/admin\r\n\r\n"),
+            Err(RequestParseError::InvalidHost)
+        ));
+        assert_eq!(
+            status(b"GET /\x01 HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Some(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[test]
+    fn read_bodies() {
+        let mut raw: &[u8] = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello\
+            GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        let request = HttpRequest::parse(&mut raw, None).unwrap().unwrap();
+        assert_eq!(request.body.as_deref(), Some(&b"hello"[..]));
+        // The next request on the connection starts right after the body
+        let request = HttpRequest::parse(&mut raw, None).unwrap().unwrap();
+        assert_eq!((request.method, request.body), (Method::Get, None));
+        assert!(raw.is_empty());
+
+        assert_eq!(
+            status(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 17\r\n\r\n"),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        for content_length in [
+            "Content-Length: 1\r\nContent-Length: 1",
+            "Content-Length: +1",
+        ] {
+            let raw = format!("POST / HTTP/1.1\r\nHost: a\r\n{}\r\n\r\na", content_length);
+            assert!(matches!(
+                parse(raw.as_bytes()),
+                Err(RequestParseError::InvalidContentLength)
+            ));
+        }
+        assert!(matches!(
+            parse(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhel"),
+            Err(RequestParseError::SocketClosed)
+        ));
+    }
+
+    #[test]
+    fn stream_bodies() {
+        let raw = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello!";
+        let mut reader = &raw[..];
+        let (mut request, framing) = HttpRequest::parse_head(&mut reader, None).unwrap().unwrap();
+        assert_eq!(framing, Framing::Length(5));
+        let stream = request.stream_body(Box::new(io::Cursor::new(reader.to_vec())), framing, None);
+        assert_eq!(request.body, None);
+
+        let mut start = [0; 2];
+        request
+            .body_stream()
+            .unwrap()
+            .read_exact(&mut start)
+            .unwrap();
+        assert_eq!(&start, b"he");
+        let mut rest = Vec::new();
+        stream.clone().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"llo");
+        stream.discard().unwrap();
+    }
+
+    #[test]
+    fn read_chunked_bodies() {
+        let mut raw: &[u8] = b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;name=value\r\nhello\r\n7 \r\n, world\r\n0\r\nChecksum: abc\r\n\r\n\
+            GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        let request = HttpRequest::parse(&mut raw, Some(12)).unwrap().unwrap();
+        assert_eq!(request.body.as_deref(), Some(&b"hello, world"[..]));
+        assert_eq!(request.trailers["checksum"], "abc");
+        let request = HttpRequest::parse(&mut raw, None).unwrap().unwrap();
+        assert_eq!(request.method, Method::Get);
+
+        let chunked = |body: &str| {
+            let raw = format!(
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n{}",
+                body
+            );
+            parse(raw.as_bytes()).map(|_| ())
+        };
+        assert!(matches!(
+            chunked("11\r\n"),
+            Err(RequestParseError::PayloadTooLarge)
+        ));
+        for body in [
+            "x\r\n",
+            "5\nhello\r\n",
+            "2\r\nhello\r\n",
+            "0\r\nbad trailer\r\n\r\n",
+        ] {
+            assert!(matches!(
+                chunked(body),
+                Err(RequestParseError::InvalidChunk)
+            ));
+        }
+        assert!(matches!(
+            chunked("5\r\nhel"),
+            Err(RequestParseError::SocketClosed)
+        ));
+
+        let framed = |headers: &str| {
+            let raw = format!("POST / HTTP/1.1\r\nHost: a\r\n{}\r\n\r\n0\r\n\r\n", headers);
+            parse(raw.as_bytes()).unwrap_err().status()
+        };
+        assert_eq!(
+            framed("Transfer-Encoding: chunked\r\nContent-Length: 5"),
+            Some(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            framed("Transfer-Encoding: gzip"),
+            Some(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            framed("Transfer-Encoding: gzip, chunked"),
+            Some(StatusCode::NOT_IMPLEMENTED)
+        );
+    }
+
+    #[test]
+    fn stream_chunked_bodies() {
+        let mut raw: &[u8] = b"3\r\nabc\r\n3\r\ndef\r\n0\r\nX-Done: yes\r\n\r\nnext";
+        let mut decoder = BodyDecoder::new(&mut raw, Framing::Chunked, None);
+        let mut start = [0; 4];
+        decoder.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"abcd");
+        assert!(decoder.trailers.is_empty());
+        decoder.discard().unwrap();
+        assert_eq!(decoder.trailers["x-done"], "yes");
+        assert_eq!(raw, b"next");
+
+        let raw = b"3\r\nabc\r\n0\r\n\r\n".to_vec();
+        let mut stream = BodyStream::new(Box::new(io::Cursor::new(raw)), Framing::Chunked, None);
+        let mut body = String::new();
+        stream.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "abc");
     }
 }
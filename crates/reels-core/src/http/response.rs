@@ -1,8 +1,14 @@
-use std::{collections::HashMap, io::Write};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::SystemTime;
 
 use super::{status::StatusCode, version::Version};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Size of the chunks a streamed body is read in
+const CHUNK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug)]
 pub struct HttpResponse {
     /// The response's status
     pub status: StatusCode,
@@ -14,7 +20,137 @@ pub struct HttpResponse {
     pub headers: HashMap<String, String>,
 
     /// The response's body
-    pub body: Vec<u8>,
+    pub body: Body,
+}
+
+/// The body of a response
+pub enum Body {
+    /// A body held in memory
+    Bytes(Vec<u8>),
+    /// A body produced chunk by chunk while the response is written
+    ///
+    /// With a known `length` exactly that many bytes are written, otherwise the body is
+    /// sent with `Transfer-Encoding: chunked`.
+    Stream {
+        chunks: Box<dyn Iterator<Item = io::Result<Vec<u8>>>>,
+        length: Option<u64>,
+    },
+}
+
+impl Body {
+    /// Stream a body from a reader, `length` is the number of bytes it yields if known
+    pub fn from_reader<R: Read + 'static>(reader: R, length: Option<u64>) -> Self {
+        Body::Stream {
+            chunks: Box::new(ReaderChunks {
+                reader,
+                done: false,
+            }),
+            length,
+        }
+    }
+
+    /// Stream a body of unknown length chunk by chunk
+    pub fn from_chunks<I>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: 'static,
+    {
+        Body::Stream {
+            chunks: Box::new(chunks.into_iter().map(Ok)),
+            length: None,
+        }
+    }
+
+    /// The bytes of an in memory body
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::Stream { .. } => None,
+        }
+    }
+
+    /// The length of the body, `None` for a stream of unknown length
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Stream { length, .. } => *length,
+        }
+    }
+
+    /// Whether the body is known to be empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::Bytes(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Self {
+        Body::Bytes(s.into_bytes())
+    }
+}
+
+/// Compare an in memory body with bytes, a stream equals nothing
+impl<T: AsRef<[u8]> + ?Sized> PartialEq<T> for Body {
+    fn eq(&self, other: &T) -> bool {
+        self.as_bytes() == Some(other.as_ref())
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Body::Stream { length, .. } => {
+                f.debug_struct("Stream").field("length", length).finish()
+            }
+        }
+    }
+}
+
+/// Reads a reader in chunks of `CHUNK_SIZE` bytes
+struct ReaderChunks<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> Iterator for ReaderChunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = vec![0; CHUNK_SIZE];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(n) => {
+                    chunk.truncate(n);
+                    return Some(Ok(chunk));
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
 }
 
 impl HttpResponse {
@@ -22,16 +158,153 @@ impl HttpResponse {
         HttpResponseBuilder::new()
     }
 
-    pub fn write<T: Write>(self, stream: &mut T) -> std::io::Result<()> {
-        write!(stream, "HTTP/1.1 {}\r\n", self.status)?;
-        self.headers.iter().for_each(|(key, value)| {
-            write!(stream, "{}: {}\r\n", key, value).unwrap();
-        });
-        write!(stream, "\r\n")?;
-        stream.write_all(&self.body)?;
-        write!(stream, "\r\n")?;
-        stream.flush()?;
-        Ok(())
+    /// The value of a header, names are compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the body is sent with `Transfer-Encoding: chunked`
+    pub fn is_chunked(&self) -> bool {
+        self.header("transfer-encoding").is_some_and(|value| {
+            value
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        })
+    }
+
+    /// Whether the status forbids a body
+    fn has_no_body(&self) -> bool {
+        let code = self.status.as_u16();
+        code < 200 || code == 204 || code == 304
+    }
+
+    /// Write the response to the connection
+    ///
+    /// A `date` header is added unless the response has one. The body is written as
+    /// announced by the headers: encoded in chunks, cut to `content-length` bytes, or until
+    /// its end when neither is set, in which case the connection has to be closed after it.
+    pub fn write<T: Write>(self, stream: &mut T) -> io::Result<()> {
+        self.write_head(stream)?;
+        if self.has_no_body() {
+            return stream.flush();
+        }
+        let framing = if self.is_chunked() {
+            Framing::Chunked
+        } else {
+            match self.header("content-length").map(str::parse) {
+                Some(Ok(length)) => Framing::Length(length),
+                Some(Err(_)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Invalid content-length header",
+                    ))
+                }
+                None => match self.body.len() {
+                    Some(length) => Framing::Length(length),
+                    None => Framing::Close,
+                },
+            }
+        };
+        let chunks: Box<dyn Iterator<Item = io::Result<Vec<u8>>>> = match self.body {
+            Body::Bytes(bytes) => Box::new(std::iter::once(Ok(bytes))),
+            Body::Stream { chunks, .. } => chunks,
+        };
+        write_body(stream, chunks, framing)?;
+        stream.flush()
+    }
+
+    /// Write the status line and headers only, as the answer to a HEAD request
+    pub fn write_head<T: Write>(&self, stream: &mut T) -> io::Result<()> {
+        let mut head = format!("{} {}\r\n", self.version, self.status);
+        let mut headers: Vec<(&String, &String)> = self.headers.iter().collect();
+        headers.sort();
+        // 1xx and 204 responses announce no length, a 304 announces the one of the
+        // cached response
+        let no_length = self.status.as_u16() < 200 || self.status == StatusCode::NO_CONTENT;
+        for (key, value) in headers {
+            // A line break in a header would let its value inject headers or a body
+            if [key, value].iter().any(|s| s.contains(['\r', '\n'])) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid header {}", key),
+                ));
+            }
+            let framing_header = key.eq_ignore_ascii_case("content-length")
+                || key.eq_ignore_ascii_case("transfer-encoding");
+            if no_length && framing_header {
+                continue;
+            }
+            head.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        if self.header("date").is_none() {
+            head.push_str(&format!(
+                "date: {}\r\n",
+                httpdate::fmt_http_date(SystemTime::now())
+            ));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())
+    }
+}
+
+/// How the end of the body is marked on the connection
+enum Framing {
+    Length(u64),
+    Chunked,
+    Close,
+}
+
+/// Write the body chunk by chunk, each chunk is flushed before the next one is produced
+/// so a slow client holds back the producer
+fn write_body<T: Write>(
+    stream: &mut T,
+    chunks: Box<dyn Iterator<Item = io::Result<Vec<u8>>>>,
+    framing: Framing,
+) -> io::Result<()> {
+    match framing {
+        Framing::Length(length) => {
+            let mut remaining = length;
+            for chunk in chunks {
+                if remaining == 0 {
+                    break;
+                }
+                let chunk = chunk?;
+                let n = chunk.len().min(remaining.try_into().unwrap_or(usize::MAX));
+                stream.write_all(&chunk[..n])?;
+                stream.flush()?;
+                remaining -= n as u64;
+            }
+            // The client waits for the missing bytes, only closing the connection helps
+            if remaining > 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Ok(())
+        }
+        Framing::Chunked => {
+            for chunk in chunks {
+                let chunk = chunk?;
+                // An empty chunk would mark the end of the body
+                if chunk.is_empty() {
+                    continue;
+                }
+                write!(stream, "{:x}\r\n", chunk.len())?;
+                stream.write_all(&chunk)?;
+                write!(stream, "\r\n")?;
+                stream.flush()?;
+            }
+            write!(stream, "0\r\n\r\n")
+        }
+        Framing::Close => {
+            for chunk in chunks {
+                stream.write_all(&chunk?)?;
+                stream.flush()?;
+            }
+            Ok(())
+        }
     }
 }
 
@@ -48,7 +321,7 @@ pub struct HttpResponseBuilder {
     pub headers: HashMap<String, String>,
 
     /// The response's body
-    pub body: Vec<u8>,
+    pub body: Body,
 }
 
 impl HttpResponseBuilder {
@@ -67,19 +340,49 @@ impl HttpResponseBuilder {
     }
 
     pub fn body(mut self, content: String) -> Self {
-        self.body = content.into_bytes();
+        self.body = content.into();
         self
     }
 
     pub fn body_bytes(mut self, content: Vec<u8>) -> Self {
-        self.body = content;
+        self.body = content.into();
         self
     }
 
+    /// Stream the body from a reader instead of holding it in memory
+    ///
+    /// Examples
+    /// ```ignore
+    /// let file = File::open(path)?;
+    /// let length = file.metadata()?.len();
+    /// HttpResponse::builder().body_reader(file, Some(length)).finalize()
+    /// ```
+    pub fn body_reader<R: Read + 'static>(mut self, reader: R, length: Option<u64>) -> Self {
+        self.body = Body::from_reader(reader, length);
+        self
+    }
+
+    /// Stream the body chunk by chunk, each chunk is sent as soon as it is produced
+    pub fn body_chunks<I>(mut self, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: 'static,
+    {
+        self.body = Body::from_chunks(chunks);
+        self
+    }
+
+    /// Build the response, the body length is announced with `content-length` if known
+    /// and `transfer-encoding: chunked` otherwise
     pub fn finalize(mut self) -> HttpResponse {
-        let n = self.body.len();
-        self.headers
-            .insert("content-length".to_owned(), n.to_string());
+        match self.body.len() {
+            Some(n) => self
+                .headers
+                .insert("content-length".to_owned(), n.to_string()),
+            None => self
+                .headers
+                .insert("transfer-encoding".to_owned(), "chunked".to_owned()),
+        };
         HttpResponse {
             status: self.status,
             version: self.version,
@@ -104,11 +407,140 @@ impl HttpResponseBuilder {
         &mut self.headers
     }
 
-    pub fn get_body(&self) -> &Vec<u8> {
+    pub fn get_body(&self) -> &Body {
         &self.body
     }
 
-    pub fn get_body_mut(&mut self) -> &mut Vec<u8> {
+    pub fn get_body_mut(&mut self) -> &mut Body {
         &mut self.body
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATE: &str = "Sun, 18 Oct 2026 12:00:00 GMT";
+
+    fn builder() -> HttpResponseBuilder {
+        HttpResponse::builder().header("date".to_owned(), DATE.to_owned())
+    }
+
+    fn written(response: HttpResponse) -> io::Result<String> {
+        let mut out = Vec::new();
+        response.write(&mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn write_response() {
+        let response = builder()
+            .header("x-id".to_owned(), "7".to_owned())
+            .header("content-type".to_owned(), "text/plain".to_owned())
+            .body("hello".to_owned())
+            .finalize();
+        assert_eq!(
+            written(response).unwrap(),
+            "HTTP/1.1 200 OK\r\n\
+             content-length: 5\r\n\
+             content-type: text/plain\r\n\
+             date: Sun, 18 Oct 2026 12:00:00 GMT\r\n\
+             x-id: 7\r\n\
+             \r\n\
+             hello"
+        );
+
+        let mut response = builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("gone".to_owned())
+            .finalize();
+        response.version = Version::HTTP_10;
+        assert_eq!(
+            written(response).unwrap(),
+            "HTTP/1.0 404 NOT_FOUND\r\ncontent-length: 4\r\ndate: Sun, 18 Oct 2026 12:00:00 GMT\r\n\r\ngone"
+        );
+    }
+
+    #[test]
+    fn write_within_declared_length() {
+        let mut response = builder().body("hello world".to_owned()).finalize();
+        response
+            .headers
+            .insert("content-length".to_owned(), "5".to_owned());
+        assert!(written(response).unwrap().ends_with("\r\n\r\nhello"));
+
+        let response = builder()
+            .status(StatusCode::NO_CONTENT)
+            .body("ignored".to_owned())
+            .finalize();
+        assert_eq!(
+            written(response).unwrap(),
+            "HTTP/1.1 204 NO_CONTENT\r\ndate: Sun, 18 Oct 2026 12:00:00 GMT\r\n\r\n"
+        );
+
+        // HEAD responses announce the body without sending it
+        let response = builder().body("hello".to_owned()).finalize();
+        let mut out = Vec::new();
+        response.write_head(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\ndate: Sun, 18 Oct 2026 12:00:00 GMT\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn write_headers() {
+        let response = HttpResponse::builder().finalize();
+        let out = written(response).unwrap();
+        let date = out
+            .lines()
+            .find_map(|line| line.strip_prefix("date: "))
+            .unwrap();
+        assert!(httpdate::parse_http_date(date).is_ok());
+
+        let response = builder()
+            .header("location".to_owned(), "/\r\nset-cookie: a=b".to_owned())
+            .finalize();
+        assert_eq!(
+            written(response).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn stream_chunked_body() {
+        let response = builder()
+            .body_chunks(vec![b"hello".to_vec(), Vec::new(), b", world!".to_vec()])
+            .finalize();
+        assert!(response.is_chunked());
+        assert_eq!(
+            written(response).unwrap(),
+            "HTTP/1.1 200 OK\r\ndate: Sun, 18 Oct 2026 12:00:00 GMT\r\ntransfer-encoding: chunked\r\n\r\n\
+             5\r\nhello\r\n8\r\n, world!\r\n0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn stream_body_of_known_length() {
+        let body = io::Cursor::new(vec![b'a'; CHUNK_SIZE + 10]);
+        let response = builder()
+            .body_reader(body, Some(CHUNK_SIZE as u64 + 4))
+            .finalize();
+        assert!(!response.is_chunked());
+        let out = written(response).unwrap();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\ndate: {}\r\n\r\n",
+            CHUNK_SIZE + 4,
+            DATE
+        );
+        assert_eq!(&out[..head.len()], head);
+        assert_eq!(out.len() - head.len(), CHUNK_SIZE + 4);
+
+        // A reader ending early cannot satisfy the announced length
+        let response = builder().body_reader(&b"short"[..], Some(10)).finalize();
+        assert_eq!(
+            written(response).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
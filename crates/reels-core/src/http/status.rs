@@ -7,25 +7,38 @@ impl StatusCode {
     pub const OK: StatusCode = StatusCode(200);
     pub const CREATED: StatusCode = StatusCode(201);
     pub const ACCEPTED: StatusCode = StatusCode(202);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
     pub const PARTIAL_CONTENT: StatusCode = StatusCode(206);
     pub const MULTIPLE_CHOICES: StatusCode = StatusCode(300);
     pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
     pub const FOUND: StatusCode = StatusCode(302);
+    pub const PERMANENT_REDIRECT: StatusCode = StatusCode(308);
     pub const BAD_REQUEST: StatusCode = StatusCode(400);
     pub const UNAUTHORIZED: StatusCode = StatusCode(401);
     pub const FORBIDDEN: StatusCode = StatusCode(403);
     pub const NOT_FOUND: StatusCode = StatusCode(404);
     pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
     pub const NOT_ACCEPTABLE: StatusCode = StatusCode(406);
+    pub const REQUEST_TIMEOUT: StatusCode = StatusCode(408);
     pub const LENGTH_REQUIRED: StatusCode = StatusCode(411);
+    pub const PAYLOAD_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const URI_TOO_LONG: StatusCode = StatusCode(414);
+    pub const UNSUPPORTED_MEDIA_TYPE: StatusCode = StatusCode(415);
     pub const IM_A_TEAPOT: StatusCode = StatusCode(418);
     pub const UPGRADE_REQUIRED: StatusCode = StatusCode(426);
     pub const TOO_MANY_REQUESTS: StatusCode = StatusCode(429);
+    pub const REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode = StatusCode(431);
     pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
     pub const NOT_IMPLEMENTED: StatusCode = StatusCode(501);
     pub const BAD_GATEWAY: StatusCode = StatusCode(502);
     pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode(503);
     pub const GATEWAY_TIMEOUT: StatusCode = StatusCode(504);
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode = StatusCode(505);
+
+    /// The numeric status code
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
 }
 
 impl Default for StatusCode {
@@ -40,25 +53,33 @@ impl fmt::Display for StatusCode {
             200 => f.write_str("200 OK"),
             201 => f.write_str("201 CREATED"),
             202 => f.write_str("202 ACCEPTED"),
+            204 => f.write_str("204 NO_CONTENT"),
             206 => f.write_str("206 PARTIAL_CONTENT"),
             300 => f.write_str("300 MULTIPLE_CHOICES"),
             301 => f.write_str("301 MOVED_PERMANENTLY"),
             302 => f.write_str("302 FOUND"),
+            308 => f.write_str("308 PERMANENT_REDIRECT"),
             400 => f.write_str("400 BAD_REQUEST"),
-            401 => f.write_str("400 UNAUTHORIZED"),
+            401 => f.write_str("401 UNAUTHORIZED"),
             403 => f.write_str("403 FORBIDDEN"),
             404 => f.write_str("404 NOT_FOUND"),
             405 => f.write_str("405 METHOD_NOT_ALLOWED"),
             406 => f.write_str("406 NOT_ACCEPTABLE"),
+            408 => f.write_str("408 REQUEST_TIMEOUT"),
             411 => f.write_str("411 LENGTH_REQUIRED"),
+            413 => f.write_str("413 PAYLOAD_TOO_LARGE"),
+            414 => f.write_str("414 URI_TOO_LONG"),
+            415 => f.write_str("415 UNSUPPORTED_MEDIA_TYPE"),
             418 => f.write_str("418 IM_A_TEAPOT"),
             426 => f.write_str("426 UPGRADE_REQUIRED"),
             429 => f.write_str("429 TOO_MANY_REQUESTS"),
+            431 => f.write_str("431 REQUEST_HEADER_FIELDS_TOO_LARGE"),
             500 => f.write_str("500 INTERNAL_SERVER_ERROR"),
             501 => f.write_str("501 NOT_IMPLEMENTED"),
             502 => f.write_str("502 BAD_GATEWAY"),
             503 => f.write_str("503 SERVICE_UNAVAILABLE"),
             504 => f.write_str("504 GATEWAY_TIMEOUT"),
+            505 => f.write_str("505 HTTP_VERSION_NOT_SUPPORTED"),
             _ => Err(fmt::Error),
         }
     }
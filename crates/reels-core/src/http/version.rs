@@ -28,6 +28,12 @@ impl Default for Version {
 }
 
 impl fmt::Debug for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use self::Http::*;
 
@@ -49,7 +55,7 @@ impl TryInto<Version> for u8 {
     fn try_into(self) -> Result<Version, Self::Error> {
         match self {
             0 => Ok(Version::HTTP_10),
-            1 => Ok(Version::HTTP_10),
+            1 => Ok(Version::HTTP_11),
             _ => Err(InvalidHttpVersion),
         }
     }
@@ -1,3 +1,11 @@
+pub mod extract;
+pub mod handler;
 pub mod http;
+pub mod middleware;
+pub mod registry;
+pub mod responder;
 pub mod router;
 pub mod server;
+
+#[doc(hidden)]
+pub use inventory;
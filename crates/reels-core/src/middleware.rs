@@ -0,0 +1,182 @@
+use crate::http::{HttpRequest, HttpResponse};
+use crate::registry::RegisteredFn;
+use serde::{Deserialize, Serialize};
+
+/// Hook invoked before routing, returning a response short-circuits the pipeline
+pub type BeforeFunc = fn(&mut HttpRequest) -> Option<HttpResponse>;
+
+/// Hook invoked on the response on its way back out
+pub type AfterFunc = fn(&HttpRequest, &mut HttpResponse);
+
+/// A middleware layer made of an optional before hook and an optional after hook
+///
+/// Layers of a router run in registration order before routing and in reverse order
+/// afterwards. When a before hook short-circuits, only the after hooks of the layers that
+/// already ran (including the short-circuiting one) see the response.
+///
+/// Examples
+/// ```ignore
+/// fn require_auth(req: &mut HttpRequest) -> Option<HttpResponse> {
+///     if req.headers.contains_key("authorization") {
+///         None
+///     } else {
+///         Some(HttpResponse::builder().status(StatusCode::UNAUTHORIZED).finalize())
+///     }
+/// }
+///
+/// let router = Router::new()
+///     .middleware(Middleware::new().before(before_hook!(require_auth)))
+///     .mount(index)?;
+/// ```
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub struct Middleware {
+    before: Option<RegisteredFn<BeforeFunc>>,
+    after: Option<RegisteredFn<AfterFunc>>,
+}
+
+impl Middleware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hook that runs before the request is routed, registered with `before_hook!`
+    pub fn before(mut self, hook: RegisteredFn<BeforeFunc>) -> Self {
+        self.before = Some(hook);
+        self
+    }
+
+    /// Set the hook that runs after the response is produced, registered with `after_hook!`
+    pub fn after(mut self, hook: RegisteredFn<AfterFunc>) -> Self {
+        self.after = Some(hook);
+        self
+    }
+
+    pub(crate) fn run_before(&self, request: &mut HttpRequest) -> Option<HttpResponse> {
+        self.before?.get()(request)
+    }
+
+    pub(crate) fn run_after(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        if let Some(hook) = self.after {
+            hook.get()(request, response)
+        }
+    }
+}
+
+/// Run `dispatch` wrapped in the given middleware layers
+///
+/// `dispatch` may decline the request by returning `None`, in which case the after hooks
+/// are skipped.
+pub(crate) fn run<F>(
+    middlewares: &[&Middleware],
+    request: &mut HttpRequest,
+    dispatch: F,
+) -> Option<HttpResponse>
+where
+    F: FnOnce(&mut HttpRequest) -> Option<HttpResponse>,
+{
+    let mut ran = 0;
+    let mut short_circuit = None;
+    for middleware in middlewares {
+        ran += 1;
+        if let Some(response) = middleware.run_before(request) {
+            short_circuit = Some(response);
+            break;
+        }
+    }
+
+    let mut response = match short_circuit {
+        Some(response) => response,
+        None => dispatch(request)?,
+    };
+    for middleware in middlewares[..ran].iter().rev() {
+        middleware.run_after(request, &mut response);
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::BoxedHandler;
+    use crate::http::{Method, StatusCode};
+    use crate::router::{PathCapture, Router, SegmentTypeMissmatch};
+    use crate::{after_hook, before_hook};
+    use std::collections::HashMap;
+
+    fn request(path: &str) -> HttpRequest {
+        HttpRequest::test(Method::Get, path)
+    }
+
+    fn echo() -> (
+        Vec<Method>,
+        &'static str,
+        BoxedHandler,
+        Option<&'static str>,
+    ) {
+        fn echo(_: PathCapture, req: &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch> {
+            let trace = req.headers.get("x-trace").cloned().unwrap_or_default();
+            Ok(HttpResponse::builder().body(trace).finalize())
+        }
+        (vec![Method::Get], "/", crate::handler_fn!(echo), None)
+    }
+
+    fn append(headers: &mut HashMap<String, String>, value: &str) {
+        headers
+            .entry("x-trace".to_owned())
+            .or_default()
+            .push_str(value);
+    }
+
+    #[test]
+    fn layers_run_in_onion_order() {
+        let router = Router::new()
+            .middleware(
+                Middleware::new()
+                    .before(before_hook!(|req| {
+                        append(&mut req.headers, "a");
+                        None
+                    }))
+                    .after(after_hook!(|_, res| append(&mut res.headers, "a"))),
+            )
+            .middleware(
+                Middleware::new()
+                    .before(before_hook!(|req| {
+                        append(&mut req.headers, "b");
+                        None
+                    }))
+                    .after(after_hook!(|_, res| append(&mut res.headers, "b"))),
+            )
+            .mount(echo)
+            .unwrap();
+
+        let response = router.route(request("/"));
+        assert_eq!(response.body, b"ab");
+        assert_eq!(response.headers["x-trace"], "ba");
+    }
+
+    #[test]
+    fn before_hook_short_circuits() {
+        let router = Router::new()
+            .middleware(
+                Middleware::new()
+                    .after(after_hook!(|_, res| { append(&mut res.headers, "outer") })),
+            )
+            .middleware(Middleware::new().before(before_hook!(|_| {
+                Some(
+                    HttpResponse::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .finalize(),
+                )
+            })))
+            .middleware(
+                Middleware::new()
+                    .after(after_hook!(|_, res| { append(&mut res.headers, "inner") })),
+            )
+            .mount(echo)
+            .unwrap();
+
+        let response = router.route(request("/"));
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers["x-trace"], "outer");
+    }
+}
@@ -0,0 +1,278 @@
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::{error, fmt};
+
+use crate::extract::{self, DecodeState, EncodeState};
+use crate::handler::{self, DecodeService, Service};
+use crate::middleware::{AfterFunc, BeforeFunc};
+use crate::router::{AcceptsFunc, HandlerFunc};
+
+/// What a registration makes available to the other processes
+#[derive(Clone, Copy)]
+pub(crate) enum Function {
+    Handler((HandlerFunc, AcceptsFunc)),
+    Before(BeforeFunc),
+    After(AfterFunc),
+    Service(DecodeService),
+    State(EncodeState, DecodeState),
+}
+
+/// A function or type registered under a stable id
+///
+/// Handlers, middleware hooks and states are sent into other processes by their id and
+/// looked up again in the registry of the receiving process, which holds the same
+/// registrations since every process runs the same module. Registrations are made with
+/// `handler_fn!`, `before_hook!`, `after_hook!`, `register_service!` and `register_state!`.
+pub struct Registration {
+    id: &'static str,
+    function: Function,
+}
+
+inventory::collect!(Registration);
+
+#[doc(hidden)]
+impl Registration {
+    pub const fn handler(id: &'static str, handler: HandlerFunc, accepts: AcceptsFunc) -> Self {
+        Self {
+            id,
+            function: Function::Handler((handler, accepts)),
+        }
+    }
+
+    pub const fn before(id: &'static str, hook: BeforeFunc) -> Self {
+        Self {
+            id,
+            function: Function::Before(hook),
+        }
+    }
+
+    pub const fn after(id: &'static str, hook: AfterFunc) -> Self {
+        Self {
+            id,
+            function: Function::After(hook),
+        }
+    }
+
+    pub const fn service<S: Service + Registered>() -> Self {
+        Self {
+            id: S::ID,
+            function: Function::Service(handler::decode_service::<S>),
+        }
+    }
+
+    pub const fn state<T>() -> Self
+    where
+        T: Serialize + DeserializeOwned + Clone + Registered + 'static,
+    {
+        Self {
+            id: T::ID,
+            function: Function::State(extract::encode_state::<T>, extract::decode_state::<T>),
+        }
+    }
+}
+
+/// A type registered under a stable id with `register_service!` or `register_state!`
+pub trait Registered {
+    const ID: &'static str;
+}
+
+thread_local! {
+    /// The registrations of the module by id, `None` for the ids registered more than once
+    static REGISTRY: HashMap<&'static str, Option<Function>> = {
+        let mut registry = HashMap::new();
+        for registration in inventory::iter::<Registration> {
+            registry
+                .entry(registration.id)
+                .and_modify(|function| *function = None)
+                .or_insert(Some(registration.function));
+        }
+        registry
+    };
+}
+
+/// Find what is registered under the id
+pub(crate) fn lookup(id: &str) -> Result<(&'static str, Function), RegistryError> {
+    REGISTRY.with(|registry| match registry.get_key_value(id) {
+        Some((id, Some(function))) => Ok((*id, *function)),
+        Some((id, None)) => Err(RegistryError::Duplicate(id.to_string())),
+        None => Err(RegistryError::Unregistered(id.to_owned())),
+    })
+}
+
+/// A function registered under a stable id, serialized as the id alone
+///
+/// Created with `handler_fn!`, `before_hook!` or `after_hook!`.
+#[derive(Clone, Copy)]
+pub struct RegisteredFn<F> {
+    id: &'static str,
+    function: F,
+}
+
+impl<F: Copy> RegisteredFn<F> {
+    #[doc(hidden)]
+    pub const fn new(id: &'static str, function: F) -> Self {
+        Self { id, function }
+    }
+
+    /// The id the function is registered under
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    pub(crate) fn get(&self) -> F {
+        self.function
+    }
+}
+
+impl<F> Serialize for RegisteredFn<F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.id.serialize(serializer)
+    }
+}
+
+macro_rules! deserialize_registered_fn {
+    ($ty:ty, $variant:ident) => {
+        impl<'de> Deserialize<'de> for RegisteredFn<$ty> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let id = String::deserialize(deserializer)?;
+                match lookup(&id).map_err(de::Error::custom)? {
+                    (id, Function::$variant(function)) => Ok(Self { id, function }),
+                    _ => Err(de::Error::custom(RegistryError::Unregistered(id))),
+                }
+            }
+        }
+    };
+}
+
+deserialize_registered_fn!((HandlerFunc, AcceptsFunc), Handler);
+deserialize_registered_fn!(BeforeFunc, Before);
+deserialize_registered_fn!(AfterFunc, After);
+
+/// Error looking up a registration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    /// Nothing of the expected kind is registered under the id
+    Unregistered(String),
+    /// The id is registered more than once
+    Duplicate(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Unregistered(id) => write!(f, "Nothing is registered as `{}`", id),
+            RegistryError::Duplicate(id) => write!(f, "`{}` is registered more than once", id),
+        }
+    }
+}
+
+impl error::Error for RegistryError {}
+
+/// Register a handler function under an id unique to the call site
+///
+/// The optional second function tells whether the handler accepts the captures of a
+/// matching path, the request falls through to the next matching route when it does not.
+/// The route macros register the handlers they generate, this is only needed for the
+/// handlers of routes written by hand.
+///
+/// Examples
+/// ```ignore
+/// fn index(_: PathCapture, _: &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch> {
+///     Ok("Hello world!".respond())
+/// }
+///
+/// fn route() -> (Vec<Method>, &'static str, BoxedHandler, Option<&'static str>) {
+///     (vec![Method::Get], "/", handler_fn!(index), None)
+/// }
+/// ```
+#[macro_export]
+macro_rules! handler_fn {
+    ($handler:expr) => {
+        $crate::handler_fn!($handler, |_| true)
+    };
+    ($handler:expr, $accepts:expr) => {{
+        const __HANDLER: $crate::router::HandlerFunc = $handler;
+        const __ACCEPTS: $crate::router::AcceptsFunc = $accepts;
+        const __ID: &str = concat!(module_path!(), "@", line!(), ":", column!());
+        $crate::inventory::submit! {
+            $crate::registry::Registration::handler(__ID, __HANDLER, __ACCEPTS)
+        }
+        $crate::handler::BoxedHandler::from_fn($crate::registry::RegisteredFn::new(
+            __ID,
+            (__HANDLER, __ACCEPTS),
+        ))
+    }};
+}
+
+/// Register a middleware hook that runs before routing
+///
+/// Examples
+/// ```ignore
+/// let router = Router::new().middleware(Middleware::new().before(before_hook!(require_auth)));
+/// ```
+#[macro_export]
+macro_rules! before_hook {
+    ($hook:expr) => {{
+        const __HOOK: $crate::middleware::BeforeFunc = $hook;
+        const __ID: &str = concat!(module_path!(), "@", line!(), ":", column!());
+        $crate::inventory::submit! {
+            $crate::registry::Registration::before(__ID, __HOOK)
+        }
+        $crate::registry::RegisteredFn::new(__ID, __HOOK)
+    }};
+}
+
+/// Register a middleware hook that runs on the response
+///
+/// Examples
+/// ```ignore
+/// let router = Router::new().middleware(Middleware::new().after(after_hook!(add_server_header)));
+/// ```
+#[macro_export]
+macro_rules! after_hook {
+    ($hook:expr) => {{
+        const __HOOK: $crate::middleware::AfterFunc = $hook;
+        const __ID: &str = concat!(module_path!(), "@", line!(), ":", column!());
+        $crate::inventory::submit! {
+            $crate::registry::Registration::after(__ID, __HOOK)
+        }
+        $crate::registry::RegisteredFn::new(__ID, __HOOK)
+    }};
+}
+
+/// Register a `Service` type under its path
+///
+/// Examples
+/// ```ignore
+/// register_service!(Greet);
+/// ```
+#[macro_export]
+macro_rules! register_service {
+    ($service:ty) => {
+        impl $crate::registry::Registered for $service {
+            const ID: &'static str = concat!(module_path!(), "::", stringify!($service));
+        }
+        $crate::inventory::submit! {
+            $crate::registry::Registration::service::<$service>()
+        }
+    };
+}
+
+/// Register a type used as router state under its path
+///
+/// Examples
+/// ```ignore
+/// register_state!(AppState);
+/// ```
+#[macro_export]
+macro_rules! register_state {
+    ($state:ty) => {
+        impl $crate::registry::Registered for $state {
+            const ID: &'static str = concat!(module_path!(), "::", stringify!($state));
+        }
+        $crate::inventory::submit! {
+            $crate::registry::Registration::state::<$state>()
+        }
+    };
+}
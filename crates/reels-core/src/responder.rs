@@ -0,0 +1,109 @@
+use serde::Serialize;
+
+use crate::http::{HttpResponse, HttpResponseBuilder, StatusCode};
+
+/// Conversion of a handler's return value into an HttpResponse
+///
+/// Examples
+/// ```ignore
+/// #[get("/users/<uid>")]
+/// fn user(uid: u32) -> Option<Json<User>> {
+///     find_user(uid).map(Json)
+/// }
+/// ```
+pub trait Responder {
+    fn respond(self) -> HttpResponse;
+}
+
+impl Responder for HttpResponse {
+    fn respond(self) -> HttpResponse {
+        self
+    }
+}
+
+impl Responder for HttpResponseBuilder {
+    fn respond(self) -> HttpResponse {
+        self.finalize()
+    }
+}
+
+impl Responder for String {
+    fn respond(self) -> HttpResponse {
+        HttpResponse::builder()
+            .header(
+                "content-type".to_owned(),
+                "text/plain; charset=utf-8".to_owned(),
+            )
+            .body(self)
+            .finalize()
+    }
+}
+
+impl Responder for &'static str {
+    fn respond(self) -> HttpResponse {
+        self.to_owned().respond()
+    }
+}
+
+impl Responder for Vec<u8> {
+    fn respond(self) -> HttpResponse {
+        HttpResponse::builder()
+            .header(
+                "content-type".to_owned(),
+                "application/octet-stream".to_owned(),
+            )
+            .body_bytes(self)
+            .finalize()
+    }
+}
+
+/// Override the status code of the inner response
+impl<T: Responder> Responder for (StatusCode, T) {
+    fn respond(self) -> HttpResponse {
+        let (status, responder) = self;
+        let mut response = responder.respond();
+        response.status = status;
+        response
+    }
+}
+
+impl<T: Responder, E: Responder> Responder for Result<T, E> {
+    fn respond(self) -> HttpResponse {
+        match self {
+            Ok(responder) => responder.respond(),
+            Err(error) => error.respond(),
+        }
+    }
+}
+
+/// `None` responds with 404 Not Found
+impl<T: Responder> Responder for Option<T> {
+    fn respond(self) -> HttpResponse {
+        match self {
+            Some(responder) => responder.respond(),
+            None => HttpResponse::builder()
+                .status(StatusCode::NOT_FOUND)
+                .finalize(),
+        }
+    }
+}
+
+/// Serialize the inner value as an `application/json` body
+///
+/// Responds with 500 Internal Server Error if the value fails to serialize.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> Responder for Json<T> {
+    fn respond(self) -> HttpResponse {
+        match serde_json::to_vec(&self.0) {
+            Ok(body) => HttpResponse::builder()
+                .header("content-type".to_owned(), "application/json".to_owned())
+                .body_bytes(body)
+                .finalize(),
+            Err(_) => HttpResponse::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .finalize(),
+        }
+    }
+}
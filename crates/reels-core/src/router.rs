@@ -1,56 +1,647 @@
-use crate::http::{HttpRequest, HttpResponse, Method};
-pub use reels_url_pattern::{PathCapture, SegmentPattern, SegmentPatternValue, UrlPattern};
-use serde::{Deserialize, Serialize};
-use std::mem;
+use crate::extract::AnyState;
+use crate::handler::{BoxedHandler, Service};
+use crate::http::{Body, HttpRequest, HttpResponse, Method, StatusCode};
+use crate::middleware::{self, Middleware};
+use crate::registry::{self, Registered, RegistryError};
+pub use reels_url_pattern::{
+    Capture, HostPattern, PathCapture, SegmentPattern, SegmentPatternValue, UrlPattern,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::any::Any;
+use std::collections::hash_map::{Entry, HashMap};
+use std::rc::Rc;
+use std::{error, fmt};
+use url::{Position, Url};
 
-use reels_url_pattern::InvalidUrlPattern;
+use reels_url_pattern::PatternTree;
+
+use reels_url_pattern::{FormatError, InvalidUrlPattern};
 
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct Router {
     routes: Vec<DefaultRoute>,
-    // middlewares: Vec<Middleware>,
-    fallback_handler: Option<HandlerPtr>,
+    /// Indices into `routes` by method and url pattern
+    tree: HashMap<Method, PatternTree<usize>>,
+    /// Url patterns of the named routes
+    names: HashMap<String, UrlPattern>,
+    middlewares: Vec<Middleware>,
+    fallback: Option<DefaultRoute>,
+    state: Option<AnyState>,
+    /// Nested routers, a route or scope refers to its enclosing scope by index
+    scopes: Vec<Scope>,
+    trailing_slash: TrailingSlash,
+    /// Reject routes shadowed by another route instead of only listing them in `conflicts`
+    strict: bool,
+    /// Routers of the virtual hosts, fixed hosts first
+    hosts: Vec<(HostPattern, Router)>,
+}
+
+/// How a path that only matches a route once a trailing slash is added or removed is handled
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// "/users/" and "/users" are different paths
+    #[default]
+    Strict,
+    /// Route the request as if the path matched the route
+    Ignore,
+    /// Answer with 308 Permanent Redirect to the path of the route
+    Redirect,
+}
+
+/// The middlewares and fallback of a router nested under a prefix
+#[derive(Serialize, Deserialize, Clone)]
+struct Scope {
+    parent: Option<ScopeId>,
+    /// Absolute prefix of the scope
+    prefix: UrlPattern,
+    middlewares: Vec<Middleware>,
+    fallback: Option<DefaultRoute>,
+    state: Option<AnyState>,
 }
 
+type ScopeId = usize;
+
 impl Router {
     pub fn new() -> Router {
         Router::default()
     }
 
-    /// Mount a service or another router on the relative path
-    pub fn mount(mut self, handler: Handler) -> Result<Self, InvalidUrlPattern> {
-        let (methods, url_pattern, handler_func) = handler();
+    /// Mount a handler on its url pattern
+    ///
+    /// A route that can never be reached because an earlier route of the same method
+    /// matches all its paths first, or that a later route hides the same way, is listed by
+    /// `conflicts`, or rejected with `RouteError::Conflict` in strict mode. Route names
+    /// must be unique.
+    pub fn mount(mut self, handler: Handler) -> Result<Self, RouteError> {
+        let (methods, url_pattern, handler, name) = handler();
+        let url_pattern = UrlPattern::parse(url_pattern)?;
+        if let Some(name) = name {
+            self.add_name(name.to_owned(), url_pattern.clone())?;
+        }
         for method in methods.into_iter() {
-            let route = DefaultRoute::new(method, url_pattern.try_into()?, handler_func);
-            self.routes.push(route);
+            let mut route = DefaultRoute::new(method, url_pattern.clone(), handler.clone());
+            route.name = name.map(str::to_owned);
+            self.add_route(route)?;
         }
         Ok(self)
     }
 
-    /// Register fallback handlers
-    pub fn fallback(mut self, handler: HandlerPtr) -> Self {
-        self.fallback_handler = Some(handler);
+    /// Mount a handler carrying serializable configuration on the url pattern
+    ///
+    /// The handler is sent into every connection process along with the router, its type
+    /// must be registered with `register_service!`.
+    ///
+    /// Examples
+    /// ```ignore
+    /// let greet = Greet { greeting: "Hello".to_owned() };
+    /// let router = Router::new().handle(Method::Get, "/", greet)?;
+    /// ```
+    pub fn handle<S: Service + Registered>(
+        mut self,
+        method: Method,
+        url_pattern: &str,
+        service: S,
+    ) -> Result<Self, RouteError> {
+        let handler = BoxedHandler::service(service);
+        let route = DefaultRoute::new(method, url_pattern.try_into()?, handler);
+        self.add_route(route)?;
+        Ok(self)
+    }
+
+    /// Mount every route of another router under the prefix
+    ///
+    /// The middlewares of the nested router only wrap its own routes and fallback, and run
+    /// inside the middlewares of this router. Its fallback handles the unmatched requests
+    /// whose path starts with the prefix. Its host routers are not nested.
+    ///
+    /// Examples
+    /// ```ignore
+    /// let api = Router::new().mount(list_users)?.mount(user)?;
+    /// let router = Router::new().mount(index)?.nest("/api/v1", api)?;
+    /// ```
+    pub fn nest(mut self, prefix: &str, router: Router) -> Result<Self, RouteError> {
+        let prefix = UrlPattern::parse(prefix)?;
+        let scope = self.scopes.len();
+        // Scopes of the nested router are renumbered to follow its own scope
+        let renumber = |id: Option<ScopeId>| Some(id.map_or(scope, |id| scope + 1 + id));
+
+        self.scopes.push(Scope {
+            parent: None,
+            prefix: prefix.clone(),
+            middlewares: router.middlewares,
+            fallback: router
+                .fallback
+                .map(|fallback| fallback.prefixed(&prefix))
+                .transpose()?,
+            state: router.state,
+        });
+        for nested in router.scopes {
+            self.scopes.push(Scope {
+                parent: renumber(nested.parent),
+                prefix: prefix.join(&nested.prefix)?,
+                middlewares: nested.middlewares,
+                fallback: nested
+                    .fallback
+                    .map(|fallback| fallback.prefixed(&prefix))
+                    .transpose()?,
+                state: nested.state,
+            });
+        }
+        for (name, url_pattern) in router.names {
+            self.add_name(name, prefix.join(&url_pattern)?)?;
+        }
+        for route in router.routes {
+            let mut route = route.prefixed(&prefix)?;
+            route.scope = renumber(route.scope);
+            self.add_route(route)?;
+        }
+        Ok(self)
+    }
+
+    /// Route the requests whose host matches the pattern to another router
+    ///
+    /// Hosts without captures are tried first, then the patterns in the order they are
+    /// registered. Requests for any other host are routed by this router. The middlewares
+    /// of this router wrap the ones of the host router, which shares the state of this
+    /// router if it has none of its own. Handlers extract the host captures with `Host<T>`.
+    ///
+    /// Examples
+    /// ```ignore
+    /// let router = Router::new()
+    ///     .mount(landing_page)?
+    ///     .host("api.example.com", Router::new().mount(list_users)?)?
+    ///     .host("<tenant>.example.com", Router::new().mount(dashboard)?)?;
+    /// ```
+    pub fn host(mut self, pattern: &str, router: Router) -> Result<Self, RouteError> {
+        let pattern = HostPattern::parse(pattern)?;
+        let index = if pattern.is_fixed() {
+            self.hosts
+                .iter()
+                .position(|(host, _)| !host.is_fixed())
+                .unwrap_or(self.hosts.len())
+        } else {
+            self.hosts.len()
+        };
+        self.hosts.insert(index, (pattern, router));
+        Ok(self)
+    }
+
+    /// Wrap every route of this router in a middleware layer
+    ///
+    /// Layers run in the order they are registered
+    pub fn middleware(mut self, middleware: Middleware) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Share application state with the handlers of this router
+    ///
+    /// The state is sent into every connection process along with the router and handlers
+    /// extract it with `State<T>`. Nested routers without a state of their own share the
+    /// state of the router they are nested in. Handles to long-lived processes can be
+    /// shared this way to let handlers message singleton services.
+    ///
+    /// The state is serialized when it is sent into a connection process, so the handles
+    /// are transferred along with it. Its type must be registered with `register_state!`.
+    ///
+    /// Examples
+    /// ```ignore
+    /// #[get("/")]
+    /// fn index(State(config): State<Config>) -> String {
+    ///     format!("Welcome to {}", config.name)
+    /// }
+    ///
+    /// register_state!(Config);
+    ///
+    /// let router = Router::new().with_state(config)?.mount(index)?;
+    /// ```
+    pub fn with_state<T>(mut self, state: T) -> Result<Self, RouteError>
+    where
+        T: Serialize + DeserializeOwned + Clone + Registered + 'static,
+    {
+        registry::lookup(T::ID)?;
+        self.state = Some(AnyState::new(state));
+        Ok(self)
+    }
+
+    /// Set how requests whose path only differs from a route by a trailing slash are handled
+    ///
+    /// Nested routers follow the policy of the router they are nested in.
+    ///
+    /// Examples
+    /// ```ignore
+    /// // "/users/" is redirected to "/users"
+    /// let router = Router::new()
+    ///     .trailing_slash(TrailingSlash::Redirect)
+    ///     .mount(list_users)?;
+    /// ```
+    pub fn trailing_slash(mut self, policy: TrailingSlash) -> Self {
+        self.trailing_slash = policy;
         self
     }
 
+    /// Fail to mount routes that are shadowed by another route
+    ///
+    /// Without it such routes are mounted and listed by `conflicts`. Only applies to the
+    /// routes mounted afterwards, including the routes of the routers nested afterwards.
+    ///
+    /// Examples
+    /// ```ignore
+    /// // Err(RouteError::Conflict(..)), "/users/<id>" is never reached
+    /// let router = Router::new().strict().mount(catch_all)?.mount(user)?;
+    /// ```
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Register the handler for requests that match no route
+    ///
+    /// The methods of the handler are ignored. Its url pattern only supplies the captures
+    /// when it matches the request path, otherwise the handler is invoked without any.
+    /// Requests are answered with 404 Not Found if there is no fallback handler or it
+    /// rejects the captures.
+    pub fn fallback(mut self, handler: Handler) -> Result<Self, RouteError> {
+        let (methods, url_pattern, handler, _) = handler();
+        registry::lookup(handler.id())?;
+        let method = methods.into_iter().next().unwrap_or(Method::Get);
+        self.fallback = Some(DefaultRoute::new(method, url_pattern.try_into()?, handler));
+        Ok(self)
+    }
+
+    fn add_name(&mut self, name: String, url_pattern: UrlPattern) -> Result<(), RouteError> {
+        match self.names.entry(name) {
+            Entry::Occupied(entry) => Err(RouteError::DuplicateName(entry.key().clone())),
+            Entry::Vacant(entry) => {
+                entry.insert(url_pattern);
+                Ok(())
+            }
+        }
+    }
+
+    fn add_route(&mut self, route: DefaultRoute) -> Result<(), RouteError> {
+        registry::lookup(route.handler.id())?;
+        if self.strict {
+            for existing in self.routes.iter().filter(|r| r.method == route.method) {
+                if existing.url_pattern.shadows(&route.url_pattern) {
+                    return Err(RouteError::Conflict(RouteConflict::new(&route, existing)));
+                }
+                if existing.url_pattern.shadowed_by(&route.url_pattern) {
+                    return Err(RouteError::Conflict(RouteConflict::new(existing, &route)));
+                }
+            }
+        }
+
+        self.tree
+            .entry(route.method.clone())
+            .or_default()
+            .insert(&route.url_pattern, self.routes.len());
+        self.routes.push(route);
+        Ok(())
+    }
+
+    /// Routes that are never reached because another route matches all their paths first
+    ///
+    /// A shadowed route still gets the requests whose captures the routes before it reject.
+    ///
+    /// Examples
+    /// ```ignore
+    /// for conflict in router.conflicts() {
+    ///     eprintln!("warning: {}", conflict); // GET /users/<name> is shadowed by ...
+    /// }
+    /// ```
+    pub fn conflicts(&self) -> Vec<RouteConflict> {
+        let mut conflicts = Vec::new();
+        for (index, earlier) in self.routes.iter().enumerate() {
+            for later in &self.routes[index + 1..] {
+                if earlier.method != later.method {
+                    continue;
+                }
+                if earlier.url_pattern.shadows(&later.url_pattern) {
+                    conflicts.push(RouteConflict::new(later, earlier));
+                } else if earlier.url_pattern.shadowed_by(&later.url_pattern) {
+                    conflicts.push(RouteConflict::new(earlier, later));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Table of the routes by method, in the order they are tried, for debugging
+    ///
+    /// Examples
+    /// ```ignore
+    /// print!("{}", router.route_table());
+    /// // GET     /users/me
+    /// // GET     /users/<id:u32>  user_show
+    /// // GET     /<path..>
+    /// ```
+    pub fn route_table(&self) -> String {
+        let mut methods: Vec<&Method> = self.tree.keys().collect();
+        methods.sort_by_key(|method| method.as_str());
+        let mut rows: Vec<(&Method, String, &str)> = Vec::new();
+        for method in methods {
+            for index in self.tree[method].values() {
+                let route = &self.routes[*index];
+                let name = route.name.as_deref().unwrap_or_default();
+                rows.push((method, route.url_pattern.to_string(), name));
+            }
+        }
+
+        let width = rows.iter().map(|(_, url, _)| url.len()).max().unwrap_or(0);
+        let mut table = String::new();
+        for (method, url, name) in rows {
+            let row = format!(
+                "{:<7} {:<width$}  {}",
+                method.as_str(),
+                url,
+                name,
+                width = width
+            );
+            table.push_str(row.trim_end());
+            table.push('\n');
+        }
+        for (pattern, router) in &self.hosts {
+            table.push_str(&format!("\nHost {}\n", pattern));
+            table.push_str(&router.route_table());
+        }
+        table
+    }
+
+    /// Build the url of the route with the given name by filling in its captures
+    ///
+    /// Names of the routes of nested routers are kept as they are and their urls include
+    /// the prefix.
+    ///
+    /// Examples
+    /// ```ignore
+    /// #[get("/users/<id:u32>", name = "user_show")]
+    /// fn user_show(id: u32) -> String {
+    ///     format!("User {}", id)
+    /// }
+    ///
+    /// let router = Router::new().nest("/api", Router::new().mount(user_show)?)?;
+    /// assert_eq!(router.url_for("user_show", &[("id", &42)])?, "/api/users/42");
+    /// ```
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &dyn fmt::Display)],
+    ) -> Result<String, UrlForError> {
+        let url_pattern = self
+            .names
+            .get(name)
+            .ok_or_else(|| UrlForError::UnknownRoute(name.to_owned()))?;
+        Ok(url_pattern.format(params)?)
+    }
+
     /// Route the request to the right handler based on the request uri prefix and method
-    pub fn route(&self, req: HttpRequest) -> HttpResponse {
-        for route in &self.routes {
-            if let Some(captures) = route.match_uri(&req) {
-                match route.invoke(captures, &req) {
-                    Ok(response) => return response,
-                    Err(SegmentTypeMissmatch) => continue,
+    pub fn route(&self, mut req: HttpRequest) -> HttpResponse {
+        req.state = self.state.as_ref().map(AnyState::value);
+        self.route_request(&mut req)
+    }
+
+    fn route_request(&self, req: &mut HttpRequest) -> HttpResponse {
+        let middlewares: Vec<&Middleware> = self.middlewares.iter().collect();
+        middleware::run(&middlewares, req, |req| Some(self.dispatch_host(req)))
+            .expect("dispatch always responds")
+    }
+
+    /// Hand the request over to the router of its host, if any
+    fn dispatch_host(&self, req: &mut HttpRequest) -> HttpResponse {
+        let host = req.url.host_str().unwrap_or_default();
+        let matched = self
+            .hosts
+            .iter()
+            .find(|(pattern, _)| pattern.match_host(host).is_some());
+        match matched {
+            Some((pattern, router)) => {
+                req.host_pattern = Some(pattern.clone());
+                if let Some(state) = &router.state {
+                    req.state = Some(state.value());
                 }
+                router.route_request(req)
+            }
+            None => {
+                // The state of the router or of the router it was mounted on by host
+                let inherited = req.state.clone();
+                self.dispatch(req, inherited.as_ref())
+            }
+        }
+    }
+
+    fn dispatch(&self, req: &mut HttpRequest, inherited: Option<&Rc<dyn Any>>) -> HttpResponse {
+        if let Some(response) = self.invoke_matching(&req.method.clone(), req, inherited) {
+            return response;
+        }
+
+        // Run the GET handler and drop the body, content-length is kept as is
+        if req.method == Method::Head {
+            if let Some(mut response) = self.invoke_matching(&Method::Get, req, inherited) {
+                response.body = Body::default();
+                return response;
+            }
+        }
+
+        let allowed = self.allowed_methods(&req.url);
+        if req.method == Method::Options && !allowed.is_empty() {
+            return HttpResponse::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header("allow".to_owned(), join_methods(&allowed))
+                .finalize();
+        }
+        // A route of the method that rejected the captures does not make the method disallowed
+        if allowed.is_empty() || allowed.contains(&req.method) {
+            if let Some(response) = self.redirect_trailing_slash(req, inherited) {
+                return response;
             }
+        } else {
+            return HttpResponse::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header("allow".to_owned(), join_methods(&allowed))
+                .finalize();
+        }
+
+        self.invoke_fallback(req, inherited).unwrap_or_else(|| {
+            HttpResponse::builder()
+                .status(StatusCode::NOT_FOUND)
+                .finalize()
+        })
+    }
+
+    /// Invoke the first route of `method` matching the request path that accepts the captures
+    ///
+    /// Matching routes are tried by precedence, fixed segments before wildcards before
+    /// kleene wildcards, and in registration order among equals. A route whose handler
+    /// does not accept the captures is passed over, the routes after the first one that
+    /// accepts them are not matched. Only then the chosen route runs inside the middlewares
+    /// and with the state of its own scope.
+    fn invoke_matching(
+        &self,
+        method: &Method,
+        req: &mut HttpRequest,
+        inherited: Option<&Rc<dyn Any>>,
+    ) -> Option<HttpResponse> {
+        // The handler gets the request mutably, the captures borrow a copy of its url
+        let url = req.url.clone();
+        let (route, captures) = self.tree.get(method)?.find_url(&url, |index, captures| {
+            let route = &self.routes[*index];
+            route.accepts(&captures).then_some((route, captures))
+        })?;
+
+        let middlewares = self.scope_middlewares(route.scope);
+        req.state = self.scope_state(route.scope, inherited);
+        middleware::run(&middlewares, req, |req| {
+            req.url_pattern = Some(route.url_pattern.clone());
+            route.invoke(captures, req).ok()
+        })
+    }
+
+    /// Apply the trailing slash policy to a request whose path matches no route
+    fn redirect_trailing_slash(
+        &self,
+        req: &mut HttpRequest,
+        inherited: Option<&Rc<dyn Any>>,
+    ) -> Option<HttpResponse> {
+        let path = req.url.path();
+        if self.trailing_slash == TrailingSlash::Strict || path == "/" {
+            return None;
+        }
+        let path = match path.strip_suffix('/') {
+            Some(path) => path.to_owned(),
+            None => format!("{}/", path),
+        };
+        let mut url = req.url.clone();
+        url.set_path(&path);
+        let matched = self
+            .tree
+            .values()
+            .any(|tree| tree.find_url(&url, |_, _| Some(())).is_some());
+        if !matched {
+            return None;
+        }
+
+        match self.trailing_slash {
+            TrailingSlash::Redirect => Some(
+                HttpResponse::builder()
+                    .status(StatusCode::PERMANENT_REDIRECT)
+                    .header(
+                        "location".to_owned(),
+                        url[Position::BeforePath..].to_owned(),
+                    )
+                    .finalize(),
+            ),
+            _ => {
+                req.url = url;
+                Some(self.dispatch(req, inherited))
+            }
+        }
+    }
+
+    /// Invoke the fallback of the innermost scope whose prefix matches the request path
+    fn invoke_fallback(
+        &self,
+        req: &mut HttpRequest,
+        inherited: Option<&Rc<dyn Any>>,
+    ) -> Option<HttpResponse> {
+        let innermost = self
+            .scopes
+            .iter()
+            .enumerate()
+            .filter(|(_, scope)| scope.prefix.match_url_prefix(&req.url).is_some())
+            .max_by_key(|(id, scope)| (scope.prefix.len(), usize::MAX - id))
+            .map(|(id, _)| id);
+
+        // Walk out to the closest scope that has a fallback
+        let mut scope = innermost;
+        while let Some(id) = scope {
+            if self.scopes[id].fallback.is_some() {
+                break;
+            }
+            scope = self.scopes[id].parent;
+        }
+        let fallback = match scope {
+            Some(id) => self.scopes[id].fallback.as_ref(),
+            None => self.fallback.as_ref(),
+        }?;
+
+        let middlewares = self.scope_middlewares(scope);
+        req.state = self.scope_state(scope, inherited);
+        middleware::run(&middlewares, req, |req| {
+            req.url_pattern = Some(fallback.url_pattern.clone());
+            let captures = fallback.match_path(req).unwrap_or_default();
+            fallback.invoke(captures, req).ok()
+        })
+    }
+
+    /// Middlewares of the scope and all its parents, outermost first
+    fn scope_middlewares(&self, mut scope: Option<ScopeId>) -> Vec<&Middleware> {
+        let mut middlewares = Vec::new();
+        while let Some(id) = scope {
+            let scope_middlewares = &self.scopes[id].middlewares;
+            middlewares.splice(0..0, scope_middlewares);
+            scope = self.scopes[id].parent;
+        }
+        middlewares
+    }
+
+    /// State of the closest scope that has one, else the state the router inherited
+    fn scope_state(
+        &self,
+        mut scope: Option<ScopeId>,
+        inherited: Option<&Rc<dyn Any>>,
+    ) -> Option<Rc<dyn Any>> {
+        while let Some(id) = scope {
+            if let Some(state) = &self.scopes[id].state {
+                return Some(state.value());
+            }
+            scope = self.scopes[id].parent;
+        }
+        self.state
+            .as_ref()
+            .map(AnyState::value)
+            .or_else(|| inherited.cloned())
+    }
+
+    /// Methods the request path can be requested with, including the implicit HEAD and OPTIONS
+    fn allowed_methods(&self, url: &Url) -> Vec<Method> {
+        // Listed in the order the routes matching first for each method were registered
+        let mut first_routes: Vec<(usize, &Method)> = self
+            .tree
+            .iter()
+            .filter_map(|(method, tree)| {
+                let first = tree.find_url(url, |index, _| Some(*index))?;
+                Some((first, method))
+            })
+            .collect();
+        first_routes.sort_by_key(|(first, _)| *first);
+        let mut allowed: Vec<Method> = first_routes
+            .into_iter()
+            .map(|(_, method)| method.clone())
+            .collect();
+        if allowed.is_empty() {
+            return allowed;
+        }
+        if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+            allowed.push(Method::Head);
         }
-        // TODO: Use fallback
-        HttpResponse::builder().finalize()
+        if !allowed.contains(&Method::Options) {
+            allowed.push(Method::Options);
+        }
+        allowed
     }
 }
 
+fn join_methods(methods: &[Method]) -> String {
+    let methods: Vec<&str> = methods.iter().map(Method::as_str).collect();
+    methods.join(", ")
+}
+
 pub trait Route: Sized {
-    fn new(method: Method, url_pattern: UrlPattern, handler: HandlerFunc) -> Self;
+    fn new(method: Method, url_pattern: UrlPattern, handler: BoxedHandler) -> Self;
     fn match_uri<'a>(&self, request: &'a HttpRequest) -> Option<PathCapture<'a>>;
+    fn match_path<'a>(&self, request: &'a HttpRequest) -> Option<PathCapture<'a>>;
+    fn accepts(&self, path_capture: &PathCapture) -> bool;
     fn invoke(
         &self,
         path_capture: PathCapture,
@@ -62,48 +653,867 @@ pub trait Route: Sized {
 pub struct DefaultRoute {
     method: Method,
     url_pattern: UrlPattern,
-    handler: HandlerPtr,
+    handler: BoxedHandler,
+    scope: Option<ScopeId>,
+    name: Option<String>,
+}
+
+impl DefaultRoute {
+    fn prefixed(mut self, prefix: &UrlPattern) -> Result<Self, InvalidUrlPattern> {
+        self.url_pattern = prefix.join(&self.url_pattern)?;
+        Ok(self)
+    }
 }
 
 impl Route for DefaultRoute {
-    fn new(method: Method, url_pattern: UrlPattern, handler: HandlerFunc) -> Self {
+    fn new(method: Method, url_pattern: UrlPattern, handler: BoxedHandler) -> Self {
         Self {
             method,
             url_pattern,
-            handler: handler as *const () as usize,
+            handler,
+            scope: None,
+            name: None,
         }
     }
 
     fn match_uri<'a>(&self, request: &'a HttpRequest) -> Option<PathCapture<'a>> {
-        // TODO
         if request.method != self.method {
             None
         } else {
-            self.url_pattern.match_url(&request.url)
+            self.match_path(request)
         }
     }
 
+    fn match_path<'a>(&self, request: &'a HttpRequest) -> Option<PathCapture<'a>> {
+        self.url_pattern.match_url(&request.url)
+    }
+
+    fn accepts(&self, path_capture: &PathCapture) -> bool {
+        self.handler.accepts(path_capture)
+    }
+
     fn invoke<'a>(
         &self,
         path_capture: PathCapture,
         request: &HttpRequest,
     ) -> Result<HttpResponse, SegmentTypeMissmatch> {
-        let handler = unsafe {
-            let pointer = self.handler as *const ();
-            mem::transmute::<*const (), HandlerFunc>(pointer)
-        };
-        handler(path_capture, request)
+        self.handler.call(path_capture, request)
     }
 }
 
 /// Handler function
 pub type HandlerFunc = fn(PathCapture, &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch>;
 
-/// Handler Trait
-pub type Handler = fn() -> (Vec<Method>, &'static str, HandlerFunc);
+/// Whether a handler function accepts the captures of a path matching its url pattern
+pub type AcceptsFunc = fn(&PathCapture) -> bool;
+
+/// Handler Trait, the methods, url pattern, handler and optional name of a route
+pub type Handler = fn() -> (
+    Vec<Method>,
+    &'static str,
+    BoxedHandler,
+    Option<&'static str>,
+);
 
 #[derive(Debug)]
 pub struct SegmentTypeMissmatch;
 
-/// A pointer to the handler function
-type HandlerPtr = usize;
+/// Error building the url of a named route with `Router::url_for`
+#[derive(Debug, PartialEq, Eq)]
+pub enum UrlForError {
+    UnknownRoute(String),
+    Format(FormatError),
+}
+
+impl From<FormatError> for UrlForError {
+    fn from(e: FormatError) -> Self {
+        UrlForError::Format(e)
+    }
+}
+
+impl fmt::Display for UrlForError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlForError::UnknownRoute(name) => write!(f, "No route is named `{}`", name),
+            UrlForError::Format(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for UrlForError {}
+
+/// A route that is never reached because another route of the method matches its paths first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteConflict {
+    pub method: Method,
+    /// Url pattern of the unreachable route
+    pub shadowed: String,
+    /// Url pattern of the route that matches first
+    pub by: String,
+}
+
+impl RouteConflict {
+    fn new(shadowed: &DefaultRoute, by: &DefaultRoute) -> Self {
+        Self {
+            method: shadowed.method.clone(),
+            shadowed: shadowed.url_pattern.to_string(),
+            by: by.url_pattern.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RouteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} is shadowed by {} {}",
+            self.method, self.shadowed, self.method, self.by
+        )
+    }
+}
+
+/// Error mounting a route on a router
+#[derive(Debug)]
+pub enum RouteError {
+    InvalidUrlPattern(InvalidUrlPattern),
+    Conflict(RouteConflict),
+    /// Another route already has the name
+    DuplicateName(String),
+    /// The handler or state is not found in the registry
+    Registry(RegistryError),
+}
+
+impl From<InvalidUrlPattern> for RouteError {
+    fn from(e: InvalidUrlPattern) -> Self {
+        RouteError::InvalidUrlPattern(e)
+    }
+}
+
+impl From<RegistryError> for RouteError {
+    fn from(e: RegistryError) -> Self {
+        RouteError::Registry(e)
+    }
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::InvalidUrlPattern(e) => e.fmt(f),
+            RouteError::Conflict(conflict) => conflict.fmt(f),
+            RouteError::DuplicateName(name) => write!(f, "A route is already named `{}`", name),
+            RouteError::Registry(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for RouteError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{FromRequest, Host, Path, State};
+    use crate::middleware::Middleware;
+    use crate::responder::Responder;
+    use crate::{after_hook, before_hook, register_service, register_state};
+    use lunatic::{Mailbox, Process};
+    use url::Url;
+
+    register_state!(String);
+    register_state!(Process<String>);
+
+    /// Answers with a fixed text
+    #[derive(Serialize, Deserialize)]
+    struct Text(String);
+
+    impl Service for Text {
+        fn call(&self, _: PathCapture, _: &HttpRequest) -> HttpResponse {
+            self.0.clone().respond()
+        }
+    }
+
+    register_service!(Text);
+
+    /// Answers with the `String` state of the router
+    #[derive(Serialize, Deserialize)]
+    struct StateText;
+
+    impl Service for StateText {
+        fn call(&self, _: PathCapture, req: &HttpRequest) -> HttpResponse {
+            match State::<String>::from_request(req) {
+                Ok(State(state)) => state.respond(),
+                Err(rejection) => rejection.respond(),
+            }
+        }
+    }
+
+    register_service!(StateText);
+
+    fn request(method: Method, path: &str) -> HttpRequest {
+        HttpRequest::test(method, path)
+    }
+
+    fn ok(_: PathCapture, _: &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch> {
+        Ok(HttpResponse::builder().body("ok".to_owned()).finalize())
+    }
+
+    fn users() -> (
+        Vec<Method>,
+        &'static str,
+        BoxedHandler,
+        Option<&'static str>,
+    ) {
+        (
+            vec![Method::Get, Method::Post],
+            "/users/<id>",
+            crate::handler_fn!(ok),
+            None,
+        )
+    }
+
+    fn not_found() -> (
+        Vec<Method>,
+        &'static str,
+        BoxedHandler,
+        Option<&'static str>,
+    ) {
+        fn not_found(
+            captures: PathCapture,
+            _: &HttpRequest,
+        ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+            let body = match &captures[..] {
+                [SegmentPatternValue::WildcardKleene(segments)] => {
+                    let segments: Vec<String> = segments.iter().map(Capture::to_string).collect();
+                    segments.join("/")
+                }
+                _ => return Err(SegmentTypeMissmatch),
+            };
+            Ok(HttpResponse::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(body)
+                .finalize())
+        }
+        (
+            vec![Method::Get],
+            "/<path..>",
+            crate::handler_fn!(not_found),
+            None,
+        )
+    }
+
+    /// Accepts the captures of a path whose only wildcard is a number
+    fn numeric_id(captures: &PathCapture) -> bool {
+        matches!(
+            &captures[..],
+            [SegmentPatternValue::Wildcard(id)] if id.raw().parse::<u32>().is_ok()
+        )
+    }
+
+    #[test]
+    fn services_are_sent_with_their_configuration() {
+        #[derive(Serialize, Deserialize)]
+        struct Greet {
+            greeting: String,
+        }
+
+        impl Service for Greet {
+            fn call(&self, captures: PathCapture, _: &HttpRequest) -> HttpResponse {
+                match &captures[..] {
+                    [SegmentPatternValue::Wildcard(name)] => {
+                        format!("{} {}!", self.greeting, name).respond()
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        register_service!(Greet);
+
+        let greet = Greet {
+            greeting: "Hello".to_owned(),
+        };
+        let router = Router::new()
+            .handle(Method::Get, "/<name>", greet)
+            .unwrap()
+            .mount(users)
+            .unwrap();
+
+        let bytes = bincode::serialize(&router).unwrap();
+        let router = bincode::deserialize::<Router>(&bytes).unwrap();
+        let response = router.route(request(Method::Get, "/reels"));
+        assert_eq!(response.body, b"Hello reels!");
+        let response = router.route(request(Method::Get, "/users/1"));
+        assert_eq!(response.body, b"ok");
+
+        // Handlers are only decoded from the id of a registration
+        let bytes = bincode::serialize(&("unknown", Vec::<u8>::new())).unwrap();
+        assert!(bincode::deserialize::<BoxedHandler>(&bytes).is_err());
+    }
+
+    #[test]
+    fn nested_routers_share_or_override_state() {
+        fn state() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            fn state(
+                _: PathCapture,
+                req: &HttpRequest,
+            ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+                let response = State::<String>::from_request(req).map(|State(state)| state);
+                Ok(response.respond())
+            }
+            (vec![Method::Get], "/state", crate::handler_fn!(state), None)
+        }
+
+        let router = Router::new()
+            .with_state("root".to_owned())
+            .unwrap()
+            .mount(state)
+            .unwrap()
+            .nest("/shared", Router::new().mount(state).unwrap())
+            .unwrap()
+            .nest(
+                "/own",
+                Router::new()
+                    .with_state("own".to_owned())
+                    .unwrap()
+                    .mount(state)
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let response = router.route(request(Method::Get, "/state"));
+        assert_eq!(response.body, b"root");
+        let response = router.route(request(Method::Get, "/shared/state"));
+        assert_eq!(response.body, b"root");
+        let response = router.route(request(Method::Get, "/own/state"));
+        assert_eq!(response.body, b"own");
+    }
+
+    #[lunatic::test]
+    fn process_handles_are_sent_with_the_state(mailbox: Mailbox<String>) {
+        /// Sends the path to the process in the state
+        #[derive(Serialize, Deserialize)]
+        struct Notify;
+
+        impl Service for Notify {
+            fn call(&self, _: PathCapture, req: &HttpRequest) -> HttpResponse {
+                let State(parent) = State::<Process<String>>::from_request(req).unwrap();
+                parent.send(req.url.path().to_owned());
+                "sent".respond()
+            }
+        }
+
+        register_service!(Notify);
+
+        let router = Router::new()
+            .with_state(mailbox.this())
+            .unwrap()
+            .handle(Method::Get, "/<name>", Notify)
+            .unwrap();
+
+        // The router is sent the way the server sends it into a connection process
+        Process::spawn(router, |router, _: Mailbox<()>| {
+            router.route(request(Method::Get, "/reels"));
+        });
+        assert_eq!(mailbox.receive(), "/reels");
+    }
+
+    #[test]
+    fn fixed_routes_take_precedence_and_mismatches_fall_through() {
+        fn by_id() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            fn by_id(
+                captures: PathCapture,
+                _: &HttpRequest,
+            ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+                match &captures[..] {
+                    [SegmentPatternValue::Wildcard(id)] if id.raw().parse::<u32>().is_ok() => {
+                        Ok(HttpResponse::builder().body("id".to_owned()).finalize())
+                    }
+                    _ => Err(SegmentTypeMissmatch),
+                }
+            }
+            (
+                vec![Method::Get],
+                "/users/<id>",
+                crate::handler_fn!(by_id, numeric_id),
+                None,
+            )
+        }
+        fn by_name() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            fn by_name(
+                _: PathCapture,
+                _: &HttpRequest,
+            ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+                Ok(HttpResponse::builder().body("name".to_owned()).finalize())
+            }
+            (
+                vec![Method::Get],
+                "/users/<name>",
+                crate::handler_fn!(by_name),
+                None,
+            )
+        }
+        fn me() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            fn me(_: PathCapture, _: &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch> {
+                Ok(HttpResponse::builder().body("me".to_owned()).finalize())
+            }
+            (vec![Method::Get], "/users/me", crate::handler_fn!(me), None)
+        }
+
+        let router = Router::new()
+            .mount(by_id)
+            .unwrap()
+            .mount(by_name)
+            .unwrap()
+            .mount(me)
+            .unwrap();
+        let response = router.route(request(Method::Get, "/users/me"));
+        assert_eq!(response.body, b"me");
+        let response = router.route(request(Method::Get, "/users/42"));
+        assert_eq!(response.body, b"id");
+        let response = router.route(request(Method::Get, "/users/alice"));
+        assert_eq!(response.body, b"name");
+
+        // The middlewares of the scope run once around the route the request falls through to
+        let scoped = router.middleware(
+            Middleware::new()
+                .before(before_hook!(|req| {
+                    req.headers
+                        .entry("x-runs".to_owned())
+                        .or_default()
+                        .push('b');
+                    None
+                }))
+                .after(after_hook!(|req, res| {
+                    let runs = format!("{}a", req.headers["x-runs"]);
+                    res.headers.insert("x-runs".to_owned(), runs);
+                })),
+        );
+        let router = Router::new().nest("/api", scoped).unwrap();
+        let response = router.route(request(Method::Get, "/api/users/alice"));
+        assert_eq!(response.body, b"name");
+        assert_eq!(response.headers["x-runs"], "ba");
+    }
+
+    #[test]
+    fn trailing_slash_policies() {
+        let router = Router::new().mount(users).unwrap();
+        let response = router.route(request(Method::Get, "/users/1/"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+        let router = router.trailing_slash(TrailingSlash::Ignore);
+        let response = router.route(request(Method::Get, "/users/1/"));
+        assert_eq!(response.body, b"ok");
+
+        let router = router.trailing_slash(TrailingSlash::Redirect);
+        let response = router.route(request(Method::Get, "/users/1/?page=2"));
+        assert_eq!(response.status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers["location"], "/users/1?page=2");
+        let response = router.route(request(Method::Get, "/posts/"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn url_for_named_routes() {
+        fn user() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            (
+                vec![Method::Get],
+                "/users/<id:u32>",
+                crate::handler_fn!(ok),
+                Some("user_show"),
+            )
+        }
+
+        let router = Router::new()
+            .nest("/api", Router::new().mount(user).unwrap())
+            .unwrap();
+        assert_eq!(
+            router.url_for("user_show", &[("id", &42)]).unwrap(),
+            "/api/users/42"
+        );
+        assert_eq!(
+            router.url_for("user_show", &[("id", &"me")]),
+            Err(UrlForError::Format(FormatError::InvalidParameter(
+                "id".to_owned()
+            )))
+        );
+        assert_eq!(
+            router.url_for("user_list", &[]),
+            Err(UrlForError::UnknownRoute("user_list".to_owned()))
+        );
+
+        let duplicate = router.clone().mount(user);
+        assert!(matches!(duplicate, Err(RouteError::DuplicateName(name)) if name == "user_show"));
+        let nested = Router::new().mount(user).unwrap();
+        let duplicate = router.nest("/v2", nested);
+        assert!(matches!(duplicate, Err(RouteError::DuplicateName(name)) if name == "user_show"));
+    }
+
+    #[test]
+    fn path_extractor_deserializes_captures() {
+        #[derive(Serialize, Deserialize)]
+        struct UserPost;
+
+        impl Service for UserPost {
+            fn call(&self, _: PathCapture, req: &HttpRequest) -> HttpResponse {
+                match Path::<(String, u32)>::from_request(req) {
+                    Ok(Path((user, id))) => format!("{} {}", user, id).respond(),
+                    Err(rejection) => rejection.respond(),
+                }
+            }
+        }
+
+        register_service!(UserPost);
+
+        let router = Router::new()
+            .handle(Method::Get, "/users/<user>/posts/<id>", UserPost)
+            .unwrap();
+
+        let response = router.route(request(Method::Get, "/users/reels/posts/42"));
+        assert_eq!(response.body, b"reels 42");
+
+        let response = router.route(request(Method::Get, "/users/reels/posts/latest"));
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.body,
+            b"Invalid path: Cannot parse path capture `id` with value `latest` as u32"
+        );
+    }
+
+    #[test]
+    fn requests_are_routed_by_host() {
+        #[derive(Serialize, Deserialize)]
+        struct Tenant;
+
+        impl Service for Tenant {
+            fn call(&self, _: PathCapture, req: &HttpRequest) -> HttpResponse {
+                match Host::<String>::from_request(req) {
+                    Ok(Host(tenant)) => format!("tenant {}", tenant).respond(),
+                    Err(rejection) => rejection.respond(),
+                }
+            }
+        }
+
+        register_service!(Tenant);
+
+        fn for_host(host: &str, path: &str) -> HttpRequest {
+            let mut req = request(Method::Get, path);
+            req.url = Url::parse(&format!("http://{}{}", host, path)).unwrap();
+            req
+        }
+
+        let tenant = Router::new().handle(Method::Get, "/", Tenant).unwrap();
+        let api = Router::new()
+            .handle(Method::Get, "/", Text("api".to_owned()))
+            .unwrap();
+        let router = Router::new()
+            .handle(Method::Get, "/", Text("landing".to_owned()))
+            .unwrap()
+            .host("<tenant>.example.com", tenant)
+            .unwrap()
+            .host("api.example.com", api)
+            .unwrap();
+
+        let response = router.route(for_host("acme.example.com", "/"));
+        assert_eq!(response.body, b"tenant acme");
+        let response = router.route(for_host("API.example.com", "/"));
+        assert_eq!(response.body, b"api");
+        let response = router.route(for_host("example.com", "/"));
+        assert_eq!(response.body, b"landing");
+        let response = router.route(for_host("acme.example.com", "/users"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+        assert_eq!(
+            router.route_table(),
+            "GET     /\n\
+             \n\
+             Host api.example.com\n\
+             GET     /\n\
+             \n\
+             Host <tenant>.example.com\n\
+             GET     /\n"
+        );
+    }
+
+    #[test]
+    fn host_routers_share_the_state_of_their_parent() {
+        let tenant = Router::new().handle(Method::Get, "/", StateText).unwrap();
+        let router = Router::new()
+            .with_state("root".to_owned())
+            .unwrap()
+            .host("<tenant>.example.com", tenant)
+            .unwrap();
+
+        let mut req = request(Method::Get, "/");
+        req.url = Url::parse("http://acme.example.com/").unwrap();
+        let response = router.route(req);
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, b"root");
+    }
+
+    #[test]
+    fn routes_can_require_query_parameters() {
+        fn search() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            (vec![Method::Get], "/search?q", crate::handler_fn!(ok), None)
+        }
+
+        let router = Router::new().mount(search).unwrap();
+        let response = router.route(request(Method::Get, "/search?q=reels"));
+        assert_eq!(response.status, StatusCode::OK);
+        let response = router.route(request(Method::Get, "/search?lang=en"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        let response = router.route(request(Method::Post, "/search?q="));
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[test]
+    fn shadowed_routes_are_reported() {
+        fn by_name() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            (
+                vec![Method::Get],
+                "/users/<name>",
+                crate::handler_fn!(ok),
+                Some("user_show"),
+            )
+        }
+
+        // The kleene wildcard is tried after the more specific routes
+        let router = Router::new()
+            .mount(not_found)
+            .unwrap()
+            .mount(users)
+            .unwrap();
+        assert!(router.conflicts().is_empty());
+
+        let router = router.mount(by_name).unwrap();
+        assert_eq!(
+            router.conflicts(),
+            vec![RouteConflict {
+                method: Method::Get,
+                shadowed: "/users/<name>".to_owned(),
+                by: "/users/<id>".to_owned(),
+            }]
+        );
+        assert_eq!(
+            router.route_table(),
+            "GET     /users/<id>\n\
+             GET     /users/<name>  user_show\n\
+             GET     /<path..>\n\
+             POST    /users/<id>\n"
+        );
+
+        let strict = Router::new().strict().mount(users).unwrap().mount(by_name);
+        assert!(matches!(strict, Err(RouteError::Conflict(_))));
+    }
+
+    #[test]
+    fn unmatched_path_is_not_found() {
+        let router = Router::new().mount(users).unwrap();
+        let response = router.route(request(Method::Get, "/posts/1"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn unmatched_method_is_not_allowed() {
+        let router = Router::new().mount(users).unwrap();
+        let response = router.route(request(Method::Delete, "/users/1"));
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers["allow"], "GET, POST, HEAD, OPTIONS");
+
+        let response = router.route(request(Method::Post, "/users/1"));
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    #[test]
+    fn rejected_captures_are_not_a_disallowed_method() {
+        fn numeric() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            fn numeric(
+                captures: PathCapture,
+                _: &HttpRequest,
+            ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+                match &captures[..] {
+                    [SegmentPatternValue::Wildcard(id)] if id.raw().parse::<u32>().is_ok() => {
+                        Ok(HttpResponse::builder().body("id".to_owned()).finalize())
+                    }
+                    _ => Err(SegmentTypeMissmatch),
+                }
+            }
+            (
+                vec![Method::Get],
+                "/users/<id>",
+                crate::handler_fn!(numeric, numeric_id),
+                None,
+            )
+        }
+
+        let router = Router::new().mount(numeric).unwrap();
+        let response = router.route(request(Method::Get, "/users/alice"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        let response = router.route(request(Method::Head, "/users/alice"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        let response = router.route(request(Method::Delete, "/users/alice"));
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+
+        let router = router.fallback(not_found).unwrap();
+        let response = router.route(request(Method::Get, "/users/alice"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        assert_eq!(response.body, b"users/alice");
+    }
+
+    #[test]
+    fn head_runs_get_handler_without_body() {
+        let router = Router::new().mount(users).unwrap();
+        let response = router.route(request(Method::Head, "/users/1"));
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers["content-length"], "2");
+    }
+
+    #[test]
+    fn options_lists_allowed_methods() {
+        let router = Router::new().mount(users).unwrap();
+        let response = router.route(request(Method::Options, "/users/1"));
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!(response.headers["allow"], "GET, POST, HEAD, OPTIONS");
+
+        let response = router.route(request(Method::Options, "/posts/1"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn nested_routes_are_prefixed_and_scoped() {
+        let api = Router::new()
+            .middleware(Middleware::new().after(after_hook!(|_, res| {
+                res.headers.insert("x-scope".to_owned(), "api".to_owned());
+            })))
+            .mount(users)
+            .unwrap()
+            .fallback(not_found)
+            .unwrap();
+        let router = Router::new()
+            .mount(users)
+            .unwrap()
+            .nest("/api/v1", api)
+            .unwrap();
+
+        let response = router.route(request(Method::Get, "/api/v1/users/1"));
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers["x-scope"], "api");
+
+        let response = router.route(request(Method::Get, "/users/1"));
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(!response.headers.contains_key("x-scope"));
+
+        let response = router.route(request(Method::Get, "/api/v1/posts"));
+        assert_eq!(response.body, b"posts");
+        assert_eq!(response.headers["x-scope"], "api");
+
+        let response = router.route(request(Method::Get, "/posts"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn fall_through_runs_the_middlewares_of_the_chosen_scope() {
+        fn by_id() -> (
+            Vec<Method>,
+            &'static str,
+            BoxedHandler,
+            Option<&'static str>,
+        ) {
+            fn by_id(
+                _: PathCapture,
+                req: &HttpRequest,
+            ) -> Result<HttpResponse, SegmentTypeMissmatch> {
+                let state = State::<String>::from_request(req).map(|State(state)| state);
+                Ok(state.respond())
+            }
+            (
+                vec![Method::Get],
+                "/<id>",
+                crate::handler_fn!(by_id, numeric_id),
+                None,
+            )
+        }
+
+        let ids = Router::new()
+            .with_state("ids".to_owned())
+            .unwrap()
+            .middleware(Middleware::new().before(before_hook!(|req| {
+                req.headers.insert("x-ids".to_owned(), "ran".to_owned());
+                None
+            })))
+            .mount(by_id)
+            .unwrap();
+        let names = Router::new()
+            .with_state("names".to_owned())
+            .unwrap()
+            .middleware(Middleware::new().after(after_hook!(|req, res| {
+                let ids = req.headers.get("x-ids").cloned().unwrap_or_default();
+                res.headers.insert("x-ids".to_owned(), ids);
+            })))
+            .handle(Method::Get, "/<name>", StateText)
+            .unwrap();
+        let router = Router::new()
+            .nest("/users", ids)
+            .unwrap()
+            .nest("/users", names)
+            .unwrap();
+
+        let response = router.route(request(Method::Get, "/users/42"));
+        assert_eq!(response.body, b"ids");
+        let response = router.route(request(Method::Get, "/users/alice"));
+        assert_eq!(response.body, b"names");
+        assert_eq!(response.headers["x-ids"], "");
+    }
+
+    #[test]
+    fn unmatched_path_uses_fallback() {
+        let router = Router::new()
+            .mount(users)
+            .unwrap()
+            .fallback(not_found)
+            .unwrap();
+        let response = router.route(request(Method::Put, "/posts/1"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        assert_eq!(response.body, b"posts/1");
+    }
+}
@@ -1,13 +1,18 @@
 use lunatic::{net, net::ToSocketAddrs, Mailbox, Process};
-use std::io::{self, BufReader, BufWriter};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::http::HttpRequest;
+use crate::http::{HttpRequest, HttpResponse, Method, Version};
 use crate::router::Router;
 
 pub struct Server {
     address: Option<SocketAddr>,
     router: Router,
+    config: ConnectionConfig,
 }
 
 impl Server {
@@ -15,6 +20,7 @@ impl Server {
         Server {
             address: None,
             router,
+            config: ConnectionConfig::default(),
         }
     }
 
@@ -23,39 +29,456 @@ impl Server {
         Ok(self)
     }
 
+    /// How long an idle connection is kept open while waiting for the next request
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.config.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Close every connection after its first response
+    pub fn disable_keep_alive(mut self) -> Self {
+        self.config.keep_alive_timeout = None;
+        self
+    }
+
+    /// Time budget for receiving a complete request once the client starts sending it
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = timeout;
+        self
+    }
+
+    /// Maximum number of requests served over a single connection
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        self.config.max_requests = Some(max);
+        self
+    }
+
+    /// Maximum size of a request body in bytes, larger ones are answered with 413 Payload
+    /// Too Large
+    pub fn max_body_size(mut self, max: usize) -> Self {
+        self.config.max_body_size = Some(max);
+        self
+    }
+
+    /// Accept request bodies of any size
+    pub fn unlimited_body_size(mut self) -> Self {
+        self.config.max_body_size = None;
+        self
+    }
+
+    /// Add a `server` header with the value to responses that have none
+    pub fn server_header(mut self, value: impl Into<String>) -> Self {
+        self.config.server_header = Some(value.into());
+        self
+    }
+
+    /// Leave request bodies on the connection for handlers to read with
+    /// `HttpRequest::body_stream` instead of buffering them into `body`
+    ///
+    /// The reads are bounded by the request timeout. The `Json`, `Form` and `Vec<u8>`
+    /// extractors read the whole body off the connection, `&[u8]` cannot borrow it. The
+    /// unread rest of a body is discarded once the handler returns.
+    pub fn stream_request_bodies(mut self) -> Self {
+        self.config.stream_bodies = true;
+        self
+    }
+
     pub fn start(self) {
-        // TODO: refactor this and add timeout for keep-alive
         if let Some(address) = self.address {
             let listener = net::TcpListener::bind(address).unwrap();
             while let Ok((tcp_stream, _peer)) = listener.accept() {
-                println!("Accepted connection");
                 // Handle connections in a new process
                 Process::spawn(
-                    (tcp_stream, self.router.clone()),
-                    |(tcp_stream, router), _: Mailbox<()>| {
-                        println!("Process started");
-                        let mut buf_reader = BufReader::with_capacity(4198, tcp_stream.clone());
-                        let mut buf_writer = BufWriter::new(tcp_stream);
-                        while let Some(request) = HttpRequest::parse(&mut buf_reader).unwrap() {
-                            println!("{}", request.url);
-                            println!(
-                                "{:?}",
-                                request
-                                    .url
-                                    .path_segments()
-                                    .unwrap()
-                                    .collect::<Vec<&str>>()
-                                    .join("/")
-                            );
-                            let response = router.route(request);
-                            // println!("{:#?}", &response);
-                            response.write(&mut buf_writer).unwrap();
-                            println!("Response written");
-                        }
-                        println!("Socket closed");
-                    },
+                    (tcp_stream, self.router.clone(), self.config.clone()),
+                    handle_connection,
                 );
             }
         }
     }
 }
+
+/// Connection lifecycle settings shipped into every connection process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionConfig {
+    /// Idle timeout between requests, `None` disables keep-alive
+    pub keep_alive_timeout: Option<Duration>,
+    /// Deadline for reading a whole request, starting from its first byte
+    pub request_timeout: Duration,
+    /// Maximum number of requests per connection, `None` for unlimited
+    pub max_requests: Option<usize>,
+    /// Maximum size of a request body in bytes, `None` for unlimited
+    pub max_body_size: Option<usize>,
+    /// Leave request bodies on the connection for the handlers to read
+    pub stream_bodies: bool,
+    /// Value of the `server` header added to responses
+    pub server_header: Option<String>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            request_timeout: Duration::from_secs(30),
+            max_requests: None,
+            max_body_size: Some(2 * 1024 * 1024),
+            stream_bodies: false,
+            server_header: None,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    /// Decide whether the connection stays open after responding to `request`
+    fn keep_alive(&self, request: &HttpRequest, served: usize) -> bool {
+        if self.keep_alive_timeout.is_none() || self.max_requests.is_some_and(|max| served >= max) {
+            return false;
+        }
+        let has_token = |token: &str| {
+            request.headers.get("connection").is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|t| t.trim().eq_ignore_ascii_case(token))
+            })
+        };
+        if has_token("close") {
+            false
+        } else if request.version == Version::HTTP_10 {
+            // HTTP/1.0 connections close by default unless the client asks otherwise
+            has_token("keep-alive")
+        } else {
+            true
+        }
+    }
+}
+
+fn handle_connection(
+    (tcp_stream, router, config): (net::TcpStream, Router, ConnectionConfig),
+    _: Mailbox<()>,
+) {
+    serve(tcp_stream, &router, &config)
+}
+
+/// Serve the requests sent over the connection until it is closed
+fn serve<S: Stream>(stream: S, router: &Router, config: &ConnectionConfig) {
+    let buf_reader = Rc::new(RefCell::new(BufReader::with_capacity(
+        4198,
+        Connection::new(stream.clone()),
+    )));
+    let mut buf_writer = BufWriter::new(stream);
+    let mut served = 0;
+    loop {
+        // Wait for the first byte of the next request, a fresh connection is expected to
+        // send right away while an idle one gets the keep-alive timeout
+        let idle_timeout = match config.keep_alive_timeout {
+            Some(timeout) if served > 0 => timeout,
+            _ => config.request_timeout,
+        };
+        buf_reader.borrow_mut().get_mut().deadline = Some(Instant::now() + idle_timeout);
+        match buf_reader.borrow_mut().fill_buf() {
+            Ok(buf) if !buf.is_empty() => {}
+            // Socket closed or idle timeout reached
+            _ => break,
+        }
+
+        buf_reader.borrow_mut().get_mut().deadline = Some(Instant::now() + config.request_timeout);
+        let mut body_stream = None;
+        let head = HttpRequest::parse_head(&mut *buf_reader.borrow_mut(), config.max_body_size);
+        let parsed = head.and_then(|head| match head {
+            Some((mut request, framing)) if config.stream_bodies => {
+                let reader = Box::new(SharedReader(buf_reader.clone()));
+                body_stream = Some(request.stream_body(reader, framing, config.max_body_size));
+                Ok(Some(request))
+            }
+            Some((mut request, framing)) => {
+                let reader = &mut *buf_reader.borrow_mut();
+                request.read_body(reader, framing, config.max_body_size)?;
+                Ok(Some(request))
+            }
+            None => Ok(None),
+        });
+        let request = match parsed {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(e) => {
+                // The rest of the connection cannot be framed, answer if possible and close
+                if let Some(status) = e.status() {
+                    let mut response = HttpResponse::builder()
+                        .status(status)
+                        .body(e.to_string())
+                        .finalize();
+                    set_connection_header(&mut response, Version::HTTP_11, false);
+                    let _ = response.write(&mut buf_writer);
+                }
+                break;
+            }
+        };
+        served += 1;
+
+        let keep_alive = config.keep_alive(&request, served);
+        let version = request.version;
+        let is_head = request.method == Method::Head;
+        let mut response = router.route(request);
+        response.version = version;
+        if version == Version::HTTP_10 {
+            // HTTP/1.0 clients cannot decode chunks, closing the connection ends the body
+            response
+                .headers
+                .retain(|key, _| !key.eq_ignore_ascii_case("transfer-encoding"));
+        }
+        if let Some(server) = &config.server_header {
+            if response.header("server").is_none() {
+                response.headers.insert("server".to_owned(), server.clone());
+            }
+        }
+        // Without a length the client reads the body until the connection is closed
+        let delimited = response.is_chunked() || response.header("content-length").is_some();
+        // Skip what the handler left of the body so the next request starts at its head
+        let body_read = body_stream.map_or(true, |body| body.discard().is_ok());
+        let keep_alive = keep_alive && delimited && body_read && !closes_connection(&response);
+        set_connection_header(&mut response, version, keep_alive);
+        let written = if is_head {
+            response
+                .write_head(&mut buf_writer)
+                .and_then(|_| buf_writer.flush())
+        } else {
+            response.write(&mut buf_writer)
+        };
+        if written.is_err() || !keep_alive {
+            break;
+        }
+    }
+}
+
+/// Whether the handler asked for the connection to be closed
+fn closes_connection(response: &HttpResponse) -> bool {
+    response.header("connection").is_some_and(|value| {
+        value
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("close"))
+    })
+}
+
+fn set_connection_header(response: &mut HttpResponse, version: Version, keep_alive: bool) {
+    response
+        .headers
+        .retain(|key, _| !key.eq_ignore_ascii_case("connection"));
+    if !keep_alive {
+        response
+            .headers
+            .insert("connection".to_owned(), "close".to_owned());
+    } else if version == Version::HTTP_10 {
+        response
+            .headers
+            .insert("connection".to_owned(), "keep-alive".to_owned());
+    }
+}
+
+/// The socket of a connection
+trait Stream: Read + Write + Clone + 'static {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for net::TcpStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Connection reader shared between the server and a streamed request body
+struct SharedReader<S>(Rc<RefCell<BufReader<Connection<S>>>>);
+
+impl<S: Stream> Read for SharedReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+/// TcpStream reader that enforces a deadline across consecutive reads
+///
+/// A plain read timeout is reset by every byte received, so a client trickling in a
+/// request one byte at a time could keep the process alive forever.
+struct Connection<S> {
+    stream: S,
+    deadline: Option<Instant>,
+}
+
+impl<S: Stream> Connection<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            deadline: None,
+        }
+    }
+}
+
+impl<S: Stream> Read for Connection<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|remaining| !remaining.is_zero())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut))?;
+            self.stream.set_read_timeout(Some(remaining))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::BoxedHandler;
+    use crate::http::StatusCode;
+    use crate::responder::Responder;
+    use crate::router::{PathCapture, SegmentTypeMissmatch};
+
+    /// Scripted client, reads time out once it has sent everything
+    #[derive(Clone, Default)]
+    struct MockStream(Rc<RefCell<Mock>>);
+
+    #[derive(Default)]
+    struct Mock {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+        read_timeouts: Vec<Duration>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.borrow_mut().input.read(buf)? {
+                0 => Err(io::ErrorKind::TimedOut.into()),
+                n => Ok(n),
+            }
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Stream for MockStream {
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+            self.0.borrow_mut().read_timeouts.extend(timeout);
+            Ok(())
+        }
+    }
+
+    fn ok() -> (
+        Vec<Method>,
+        &'static str,
+        BoxedHandler,
+        Option<&'static str>,
+    ) {
+        fn ok(_: PathCapture, _: &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch> {
+            Ok("ok".respond())
+        }
+        (vec![Method::Get], "/", crate::handler_fn!(ok), None)
+    }
+
+    fn close() -> (
+        Vec<Method>,
+        &'static str,
+        BoxedHandler,
+        Option<&'static str>,
+    ) {
+        fn close(_: PathCapture, _: &HttpRequest) -> Result<HttpResponse, SegmentTypeMissmatch> {
+            Ok(HttpResponse::builder()
+                .header("connection".to_owned(), "keep-alive, Close".to_owned())
+                .body("bye".to_owned())
+                .finalize())
+        }
+        (vec![Method::Get], "/close", crate::handler_fn!(close), None)
+    }
+
+    /// Serve the requests and return the responses and the read timeouts
+    fn serve_requests(config: &ConnectionConfig, input: &str) -> (String, Vec<Duration>) {
+        let router = Router::new().mount(ok).unwrap().mount(close).unwrap();
+        let stream = MockStream::default();
+        stream.0.borrow_mut().input = io::Cursor::new(input.as_bytes().to_vec());
+        serve(stream.clone(), &router, config);
+        let mock = stream.0.borrow();
+        let output = String::from_utf8(mock.output.clone()).unwrap();
+        (output, mock.read_timeouts.clone())
+    }
+
+    const GET: &str = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    #[test]
+    fn idle_connections_wait_for_the_keep_alive_timeout() {
+        let config = ConnectionConfig {
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            request_timeout: Duration::from_secs(30),
+            ..ConnectionConfig::default()
+        };
+        let (output, timeouts) = serve_requests(&config, GET);
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+        assert!(!output.contains("connection: close"));
+
+        // The request is read within the request timeout, the idle wait after it is
+        // bounded by the keep-alive timeout
+        let first = timeouts.first().unwrap();
+        assert!(*first > Duration::from_secs(5) && *first <= Duration::from_secs(30));
+        let last = timeouts.last().unwrap();
+        assert!(*last > Duration::from_secs(4) && *last <= Duration::from_secs(5));
+    }
+
+    #[test]
+    fn incomplete_requests_time_out() {
+        let (output, _) = serve_requests(&ConnectionConfig::default(), "GET / HTTP/1.1\r\nHo");
+        assert!(output.starts_with("HTTP/1.1 408 REQUEST_TIMEOUT\r\n"));
+        assert!(output.contains("connection: close"));
+
+        let mut connection = Connection::new(MockStream::default());
+        connection.deadline = Some(Instant::now());
+        let e = connection.read(&mut [0; 8]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn connections_close_after_max_requests() {
+        let config = ConnectionConfig {
+            max_requests: Some(2),
+            ..ConnectionConfig::default()
+        };
+        let (output, _) = serve_requests(&config, &GET.repeat(3));
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 2);
+        let (first, last) = output.rsplit_once("HTTP/1.1 200 OK").unwrap();
+        assert!(!first.contains("connection: close"));
+        assert!(last.contains("connection: close"));
+    }
+
+    #[test]
+    fn http_10_connections_close_unless_kept_alive() {
+        let config = ConnectionConfig::default();
+        let request = "GET / HTTP/1.0\r\n\r\n";
+        let (output, _) = serve_requests(&config, &request.repeat(2));
+        assert_eq!(output.matches("HTTP/1.0 200 OK").count(), 1);
+        assert!(output.contains("connection: close"));
+
+        let request = "GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n";
+        let (output, _) = serve_requests(&config, &request.repeat(2));
+        assert_eq!(output.matches("HTTP/1.0 200 OK").count(), 2);
+        assert_eq!(output.matches("connection: keep-alive").count(), 2);
+    }
+
+    #[test]
+    fn handlers_can_close_the_connection() {
+        let input = format!("GET /close HTTP/1.1\r\nHost: localhost\r\n\r\n{}", GET);
+        let (output, _) = serve_requests(&ConnectionConfig::default(), &input);
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+        assert!(output.contains("connection: close"));
+        assert!(output.ends_with("bye"));
+
+        let response = HttpResponse::builder()
+            .status(StatusCode::OK)
+            .header("Connection".to_owned(), "close, upgrade".to_owned())
+            .finalize();
+        assert!(closes_connection(&response));
+    }
+}
@@ -4,6 +4,8 @@ use syn::parse::{Parse, ParseStream};
 pub struct Args {
     pub methods: Vec<String>,
     pub url: UrlPattern,
+    /// Name of the route for reverse routing
+    pub name: Option<String>,
 }
 
 impl Parse for Args {
@@ -11,9 +13,27 @@ impl Parse for Args {
         let path: syn::LitStr = input.parse()?;
         let url = UrlPattern::parse(&path.value())
             .map_err(|e| syn::Error::new(path.span(), format!("Not a valid url pattern {}", e)))?;
+
+        let mut name = None;
+        while !input.is_empty() {
+            input.parse::<syn::Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: syn::Ident = input.parse()?;
+            input.parse::<syn::Token![=]>()?;
+            let value: syn::LitStr = input.parse()?;
+            match key.to_string().as_str() {
+                "name" if name.is_none() => name = Some(value.value()),
+                "name" => return Err(syn::Error::new(key.span(), "Duplicate route name")),
+                _ => return Err(syn::Error::new(key.span(), "Unknown route argument")),
+            }
+        }
+
         Ok(Self {
             methods: Vec::new(),
             url,
+            name,
         })
     }
 }
@@ -40,7 +60,7 @@ impl Method {
             "POST" => Ok(Method::Post),
             "PATCH" => Ok(Method::Patch),
             "TRACE" => Ok(Method::Trace),
-            "Delete" => Ok(Method::Delete),
+            "DELETE" => Ok(Method::Delete),
             "CONNECT" => Ok(Method::Connect),
             "OPTIONS" => Ok(Method::Options),
             _ => Err(()),
@@ -1,7 +1,7 @@
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use quote::quote;
-use syn_mid::{FnArg, ItemFn};
+use quote::{format_ident, quote};
+use syn_mid::{FnArg, ItemFn, Pat};
 
 use crate::args::Args;
 
@@ -11,37 +11,47 @@ pub fn expand(args: Args, func: ItemFn) -> TokenStream {
         .iter()
         .map(|method| syn::Ident::new(method, Span::call_site()));
     let url_pattern = args.url.to_string();
+    let name = match &args.name {
+        Some(name) => quote! { Some(#name) },
+        None => quote! { None },
+    };
+    let captured: Vec<&str> = args.url.idents().map(|ident| ident.as_str()).collect();
     let vis = &func.vis;
     let ident = &func.sig.ident;
     let inputs = &func.sig.inputs;
-    let arg_types: Vec<proc_macro2::TokenStream> = inputs
+    // Captures are bound to locals in pattern order first so that `&str` arguments can
+    // borrow from them, arguments then pick their capture by name
+    let capture_bindings: Vec<syn::Ident> = (0..captured.len())
+        .map(|index| format_ident!("__capture_{}", index))
+        .collect();
+    // The conversions of the captures alone decide whether the handler accepts a path
+    let mut capture_checks = Vec::new();
+    let arg_values: Vec<proc_macro2::TokenStream> = inputs
         .iter()
         .map(|arg| match arg {
-            FnArg::Typed(arg) => arg.ty.clone(),
+            FnArg::Typed(arg) => arg,
             _ => unreachable!(),
         })
-        .map(|ty| {
-            let ty_str = quote! { #ty }.to_string();
-            if ty_str == "& str" {
-                quote! {
-                    match captures.next() {
-                        Some(SegmentPatternValue::Wildcard(v)) => v,
-                        _ => return Err(reels_core::router::SegmentTypeMissmatch),
-                    }
-                }
-            } else if ty_str == "Vec < & str >" {
-                quote! {
-                    match captures.next() {
-                        Some(SegmentPatternValue::WildcardKleene(v)) => v,
-                        _ => return Err(reels_core::router::SegmentTypeMissmatch),
-                    }
+        .map(|arg| {
+            let ty = &arg.ty;
+            let capture = match &*arg.pat {
+                Pat::Ident(pat) => {
+                    let name = pat.ident.to_string();
+                    captured.iter().position(|ident| *ident == name)
                 }
+                _ => None,
+            };
+            if let Some(index) = capture {
+                let value = expand_capture(&capture_bindings[index], ty);
+                capture_checks.push(value.clone());
+                value
             } else {
                 quote! {
-                    match captures.next() {
-                        Some(SegmentPatternValue::Wildcard(v)) =>
-                            v.parse::<#ty>().map_err(|_| reels_core::router::SegmentTypeMissmatch)?,
-                        _ => return Err(reels_core::router::SegmentTypeMissmatch),
+                    match <#ty as reels_core::extract::FromRequest>::from_request(request) {
+                        Ok(v) => v,
+                        Err(rejection) => {
+                            return Ok(reels_core::responder::Responder::respond(rejection))
+                        }
                     }
                 }
             }
@@ -49,7 +59,12 @@ pub fn expand(args: Args, func: ItemFn) -> TokenStream {
         .collect();
 
     let output = quote! {
-        #vis fn #ident() -> (Vec<reels::http::Method>, &'static str, reels_core::router::HandlerFunc) {
+        #vis fn #ident() -> (
+            Vec<reels::http::Method>,
+            &'static str,
+            reels_core::handler::BoxedHandler,
+            Option<&'static str>,
+        ) {
             fn #ident(
                 captures: reels_core::router::PathCapture,
                 request: &reels_core::http::HttpRequest
@@ -57,13 +72,123 @@ pub fn expand(args: Args, func: ItemFn) -> TokenStream {
                 #func
 
                 let mut captures = captures.into_iter();
-                Ok(#ident(
-                    #(#arg_types)*
-                ))
+                #(let #capture_bindings = captures.next();)*
+                Ok(reels_core::responder::Responder::respond(#ident(
+                    #(#arg_values),*
+                )))
+            }
+
+            fn __accepts(captures: &reels_core::router::PathCapture) -> bool {
+                fn check(
+                    captures: &reels_core::router::PathCapture
+                ) -> Result<reels_core::http::HttpResponse, reels_core::router::SegmentTypeMissmatch> {
+                    let mut captures = captures.iter();
+                    #(let #capture_bindings = captures.next();)*
+                    #(let _ = #capture_checks;)*
+                    Ok(reels_core::http::HttpResponse::builder().finalize())
+                }
+                check(captures).is_ok()
             }
 
-            (vec![#(reels::http::Method::#methods)*], #url_pattern, #ident)
+            (
+                vec![#(reels::http::Method::#methods),*],
+                #url_pattern,
+                reels_core::handler_fn!(#ident, __accepts),
+                #name,
+            )
         }
     };
     output.into()
 }
+
+/// Convert the path capture bound to `binding` into the argument type
+///
+/// `Option<T>` arguments accept the captures of optional segments. Captures that do not
+/// decode to valid UTF-8 are answered with 400 Bad Request.
+fn expand_capture(binding: &syn::Ident, ty: &syn::Type) -> proc_macro2::TokenStream {
+    if let Some(ty) = option_inner(ty) {
+        let value = expand_value(ty);
+        return quote! {
+            match &#binding {
+                Some(SegmentPatternValue::Missing) => None,
+                Some(SegmentPatternValue::Wildcard(v)) => Some(#value),
+                _ => return Err(reels_core::router::SegmentTypeMissmatch),
+            }
+        };
+    }
+
+    let ty_str = quote! { #ty }.to_string();
+    if ty_str == "Vec < & str >" {
+        let invalid_utf8 = invalid_utf8();
+        quote! {
+            match &#binding {
+                Some(SegmentPatternValue::WildcardKleene(v)) => {
+                    match v.iter().map(|v| v.decoded()).collect::<Result<Vec<&str>, _>>() {
+                        Ok(v) => v,
+                        Err(_) => #invalid_utf8,
+                    }
+                }
+                _ => return Err(reels_core::router::SegmentTypeMissmatch),
+            }
+        }
+    } else {
+        let value = expand_value(ty);
+        quote! {
+            match &#binding {
+                Some(SegmentPatternValue::Wildcard(v)) => #value,
+                _ => return Err(reels_core::router::SegmentTypeMissmatch),
+            }
+        }
+    }
+}
+
+/// Convert the decoded value of the capture `v` into the type
+fn expand_value(ty: &syn::Type) -> proc_macro2::TokenStream {
+    let invalid_utf8 = invalid_utf8();
+    let decoded = quote! {
+        match v.decoded() {
+            Ok(v) => v,
+            Err(_) => #invalid_utf8,
+        }
+    };
+    let ty_str = quote! { #ty }.to_string();
+    if ty_str == "& str" {
+        decoded
+    } else {
+        quote! {
+            #decoded.parse::<#ty>().map_err(|_| reels_core::router::SegmentTypeMissmatch)?
+        }
+    }
+}
+
+fn invalid_utf8() -> proc_macro2::TokenStream {
+    quote! {
+        return Ok(reels_core::responder::Responder::respond(
+            reels_core::extract::Rejection::new(
+                reels_core::http::StatusCode::BAD_REQUEST,
+                "Path segment is not valid UTF-8",
+            )
+        ))
+    }
+}
+
+/// The `T` of an `Option<T>` type
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let path = match ty {
+        syn::Type::Path(ty) if ty.qself.is_none() => &ty.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match args.args.first()? {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
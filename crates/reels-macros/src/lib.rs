@@ -11,6 +11,11 @@ use expand::expand;
 
 /// Define HTTP request handler with typed url path capture(s)
 ///
+/// The handler can return any type implementing `reels::responder::Responder`. Arguments
+/// named after a capture of the url pattern receive its value whatever their position,
+/// the captures can also be taken together with the `Path<T>` extractor. Routes given a
+/// `name` can be turned back into urls with `Router::url_for`.
+///
 /// Examples
 /// ```ignore
 /// #[route("/users/<username>")]
@@ -20,6 +25,11 @@ use expand::expand;
 ///         .body(format!("Hi, {}", username).to_owned())
 ///         .finalize()
 /// }
+///
+/// #[route("/posts/<id:u32>", name = "post")]
+/// fn post(id: u32) -> String {
+///     format!("Post {}", id)
+/// }
 /// ```
 #[proc_macro_attribute]
 pub fn route(args: TokenStream, item: TokenStream) -> TokenStream {
@@ -92,7 +102,7 @@ pub fn put(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Put".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -118,7 +128,7 @@ pub fn head(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Head".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -144,7 +154,7 @@ pub fn post(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Post".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -170,7 +180,7 @@ pub fn patch(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Patch".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -196,7 +206,7 @@ pub fn trace(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Trace".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -222,7 +232,7 @@ pub fn delete(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Delete".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -248,7 +258,7 @@ pub fn connect(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Connect".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
@@ -274,7 +284,7 @@ pub fn option(args: TokenStream, item: TokenStream) -> TokenStream {
         Ok(args) => args,
         Err(e) => return token_stream_with_error(item, e),
     };
-    args.methods.push("Get".to_string());
+    args.methods.push("Options".to_string());
 
     match syn::parse(item.clone()) {
         Ok(it) => expand(args, it),
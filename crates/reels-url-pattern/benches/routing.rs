@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use reels_url_pattern::{PatternTree, UrlPattern};
+
+/// A route table shaped like a REST api with `resources` resources
+fn patterns(resources: usize) -> Vec<UrlPattern> {
+    let mut patterns = Vec::new();
+    for i in 0..resources {
+        for pattern in [
+            format!("/api/v1/resource{}", i),
+            format!("/api/v1/resource{}/<id>", i),
+            format!("/api/v1/resource{}/<id>/edit", i),
+            format!("/api/v1/resource{}/<id>/children/<child>", i),
+            format!("/static/resource{}/<path..>", i),
+        ] {
+            patterns.push(UrlPattern::parse(&pattern).unwrap());
+        }
+    }
+    patterns
+}
+
+fn routing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("routing");
+    for resources in [10, 100] {
+        let patterns = patterns(resources);
+        let mut tree = PatternTree::new();
+        for (index, pattern) in patterns.iter().enumerate() {
+            tree.insert(pattern, index);
+        }
+        // The last resource is the worst case for the linear scan
+        let path = format!("/api/v1/resource{}/42/children/7", resources - 1);
+
+        group.bench_with_input(
+            BenchmarkId::new("linear", patterns.len()),
+            &path,
+            |b, path| {
+                b.iter(|| {
+                    patterns
+                        .iter()
+                        .position(|pattern| pattern.match_str(black_box(path)).is_some())
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("tree", patterns.len()),
+            &path,
+            |b, path| b.iter(|| tree.find_str(black_box(path), |index, _| Some(*index))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, routing);
+criterion_main!(benches);
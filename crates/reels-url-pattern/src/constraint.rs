@@ -0,0 +1,159 @@
+use std::fmt;
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Restriction on the values a wildcard capture accepts
+///
+/// Written after the identifier of the capture, either as a type name like
+/// "\<id:u32\>" and "\<name:uuid\>" or as a regular expression like "\<slug:[a-z0-9-]+\>"
+/// that has to match the whole segment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Constraint {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    F32,
+    F64,
+    Bool,
+    Uuid,
+    Regex(SegmentRegex),
+}
+
+impl Constraint {
+    pub(crate) fn parse(s: &str) -> Result<Self, regex::Error> {
+        let constraint = match s {
+            "u8" => Constraint::U8,
+            "u16" => Constraint::U16,
+            "u32" => Constraint::U32,
+            "u64" => Constraint::U64,
+            "u128" => Constraint::U128,
+            "usize" => Constraint::Usize,
+            "i8" => Constraint::I8,
+            "i16" => Constraint::I16,
+            "i32" => Constraint::I32,
+            "i64" => Constraint::I64,
+            "i128" => Constraint::I128,
+            "isize" => Constraint::Isize,
+            "f32" => Constraint::F32,
+            "f64" => Constraint::F64,
+            "bool" => Constraint::Bool,
+            "uuid" => Constraint::Uuid,
+            _ => Constraint::Regex(SegmentRegex::new(s)?),
+        };
+        Ok(constraint)
+    }
+
+    /// Whether the segment is a valid value for the capture
+    pub fn matches(&self, segment: &str) -> bool {
+        match self {
+            Constraint::U8 => segment.parse::<u8>().is_ok(),
+            Constraint::U16 => segment.parse::<u16>().is_ok(),
+            Constraint::U32 => segment.parse::<u32>().is_ok(),
+            Constraint::U64 => segment.parse::<u64>().is_ok(),
+            Constraint::U128 => segment.parse::<u128>().is_ok(),
+            Constraint::Usize => segment.parse::<usize>().is_ok(),
+            Constraint::I8 => segment.parse::<i8>().is_ok(),
+            Constraint::I16 => segment.parse::<i16>().is_ok(),
+            Constraint::I32 => segment.parse::<i32>().is_ok(),
+            Constraint::I64 => segment.parse::<i64>().is_ok(),
+            Constraint::I128 => segment.parse::<i128>().is_ok(),
+            Constraint::Isize => segment.parse::<isize>().is_ok(),
+            Constraint::F32 => segment.parse::<f32>().is_ok(),
+            Constraint::F64 => segment.parse::<f64>().is_ok(),
+            Constraint::Bool => segment.parse::<bool>().is_ok(),
+            Constraint::Uuid => is_uuid(segment),
+            Constraint::Regex(regex) => regex.is_match(segment),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Constraint::U8 => "u8",
+            Constraint::U16 => "u16",
+            Constraint::U32 => "u32",
+            Constraint::U64 => "u64",
+            Constraint::U128 => "u128",
+            Constraint::Usize => "usize",
+            Constraint::I8 => "i8",
+            Constraint::I16 => "i16",
+            Constraint::I32 => "i32",
+            Constraint::I64 => "i64",
+            Constraint::I128 => "i128",
+            Constraint::Isize => "isize",
+            Constraint::F32 => "f32",
+            Constraint::F64 => "f64",
+            Constraint::Bool => "bool",
+            Constraint::Uuid => "uuid",
+            Constraint::Regex(regex) => regex.as_str(),
+        };
+        f.write_str(name)
+    }
+}
+
+/// Hyphenated uuid like "67e55044-10b1-426f-9247-bb680e5fe0c8"
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Regular expression anchored to match a whole segment, serialized as its source
+#[derive(Debug, Clone)]
+pub struct SegmentRegex {
+    source: String,
+    regex: Regex,
+}
+
+impl SegmentRegex {
+    fn new(source: &str) -> Result<Self, regex::Error> {
+        let regex = Regex::new(&format!("^(?:{})$", source))?;
+        Ok(Self {
+            source: source.to_owned(),
+            regex,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, segment: &str) -> bool {
+        self.regex.is_match(segment)
+    }
+}
+
+impl PartialEq for SegmentRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for SegmentRegex {}
+
+impl Serialize for SegmentRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for SegmentRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        SegmentRegex::new(&source).map_err(de::Error::custom)
+    }
+}
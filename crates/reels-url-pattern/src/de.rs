@@ -0,0 +1,510 @@
+use std::{error, fmt};
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::str::FromStr;
+
+use crate::{Capture, Ident, SegmentPatternValue, UrlPattern};
+
+/// Error deserializing the captures of a path with `UrlPattern::deserialize_captures` or
+/// a query string with `deserialize_query`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeserializeError(pub(crate) String);
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for DeserializeError {}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeserializeError(msg.to_string())
+    }
+}
+
+/// A single value of a path or query string, parsed into the primitive types
+pub(crate) trait ParseValue<'p> {
+    /// What the value is for error messages, like "path capture `id`"
+    fn describe(&self) -> String;
+
+    fn value(&self) -> Result<&'p str, DeserializeError>;
+
+    fn parse<T: FromStr>(&self, ty: &str) -> Result<T, DeserializeError> {
+        let value = self.value()?;
+        value.parse().map_err(|_| {
+            DeserializeError(format!(
+                "Cannot parse {} with value `{}` as {}",
+                self.describe(),
+                value,
+                ty
+            ))
+        })
+    }
+}
+
+/// Implement the deserializer methods of the primitive types with `ParseValue::parse`
+macro_rules! deserialize_parsed {
+    () => {
+        deserialize_parsed! {
+            deserialize_bool visit_bool bool
+            deserialize_i8 visit_i8 i8
+            deserialize_i16 visit_i16 i16
+            deserialize_i32 visit_i32 i32
+            deserialize_i64 visit_i64 i64
+            deserialize_i128 visit_i128 i128
+            deserialize_u8 visit_u8 u8
+            deserialize_u16 visit_u16 u16
+            deserialize_u32 visit_u32 u32
+            deserialize_u64 visit_u64 u64
+            deserialize_u128 visit_u128 u128
+            deserialize_f32 visit_f32 f32
+            deserialize_f64 visit_f64 f64
+            deserialize_char visit_char char
+        }
+    };
+    ($($method:ident $visit:ident $ty:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.parse::<$ty>(stringify!($ty))?)
+            }
+        )*
+    };
+}
+
+pub(crate) use deserialize_parsed;
+
+impl UrlPattern {
+    /// Deserialize the captures of a path matched by this pattern
+    ///
+    /// Structs and maps take the captures by name, tuples and sequences take them in the
+    /// order they appear in the pattern and any other type takes the only capture. Values
+    /// are parsed from the decoded segments, missing optional captures are `None` and
+    /// kleene captures are sequences of segments.
+    ///
+    /// Examples
+    /// ```
+    /// # use reels_url_pattern::UrlPattern;
+    /// #[derive(serde::Deserialize)]
+    /// struct Post {
+    ///     id: u32,
+    ///     user: String,
+    /// }
+    ///
+    /// let pattern = UrlPattern::parse("/users/<user>/posts/<id>").unwrap();
+    /// let captures = pattern.match_str("/users/reels/posts/42").unwrap();
+    /// let post: Post = pattern.deserialize_captures(&captures).unwrap();
+    /// assert_eq!((post.user.as_str(), post.id), ("reels", 42));
+    ///
+    /// let (user, id): (String, u32) = pattern.deserialize_captures(&captures).unwrap();
+    /// assert_eq!((user.as_str(), id), ("reels", 42));
+    /// ```
+    pub fn deserialize_captures<T: DeserializeOwned>(
+        &self,
+        captures: &[SegmentPatternValue],
+    ) -> Result<T, DeserializeError> {
+        let names: Vec<&str> = self.idents().map(Ident::as_str).collect();
+        deserialize_captures(&names, captures)
+    }
+}
+
+/// Deserialize captures along with the identifiers they were captured by
+pub(crate) fn deserialize_captures<T: DeserializeOwned>(
+    names: &[&str],
+    captures: &[SegmentPatternValue],
+) -> Result<T, DeserializeError> {
+    T::deserialize(CapturesDeserializer {
+        names,
+        values: captures,
+    })
+}
+
+/// The captures of a path along with the identifiers they were captured by
+struct CapturesDeserializer<'p, 'a> {
+    names: &'p [&'p str],
+    values: &'p [SegmentPatternValue<'a>],
+}
+
+impl<'p, 'a> CapturesDeserializer<'p, 'a> {
+    fn single(&self) -> Result<CaptureDeserializer<'p, 'a>, DeserializeError> {
+        match (self.names, self.values) {
+            ([name], [value]) => Ok(CaptureDeserializer::new(name, value)),
+            _ => Err(DeserializeError(format!(
+                "Expected a single path capture, found {}",
+                self.values.len()
+            ))),
+        }
+    }
+
+    fn captures(&self) -> impl Iterator<Item = CaptureDeserializer<'p, 'a>> {
+        self.names
+            .iter()
+            .zip(self.values)
+            .map(|(name, value)| CaptureDeserializer::new(name, value))
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'p, 'a> de::Deserializer<'de> for CapturesDeserializer<'p, 'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.values.len() == 1 {
+            self.single()?.deserialize_any(visitor)
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf deserialize_option
+        deserialize_unit deserialize_identifier deserialize_ignored_any
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Captures::new(self.captures()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if len != self.values.len() {
+            return Err(DeserializeError(format!(
+                "Expected {} path captures, found {}",
+                len,
+                self.values.len()
+            )));
+        }
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(Captures::new(self.captures()))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+}
+
+/// Access to the captures as a sequence or as a map keyed by their identifiers
+struct Captures<'p, 'a, I: Iterator<Item = CaptureDeserializer<'p, 'a>>> {
+    captures: I,
+    value: Option<CaptureDeserializer<'p, 'a>>,
+}
+
+impl<'p, 'a, I: Iterator<Item = CaptureDeserializer<'p, 'a>>> Captures<'p, 'a, I> {
+    fn new(captures: I) -> Self {
+        Self {
+            captures,
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'p, 'a, I> SeqAccess<'de> for Captures<'p, 'a, I>
+where
+    I: Iterator<Item = CaptureDeserializer<'p, 'a>>,
+{
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.captures
+            .next()
+            .map(|capture| seed.deserialize(capture))
+            .transpose()
+    }
+}
+
+impl<'de, 'p, 'a, I> MapAccess<'de> for Captures<'p, 'a, I>
+where
+    I: Iterator<Item = CaptureDeserializer<'p, 'a>>,
+{
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let capture = match self.captures.next() {
+            Some(capture) => capture,
+            None => return Ok(None),
+        };
+        let name = capture.name;
+        self.value = Some(capture);
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let capture = self
+            .value
+            .take()
+            .ok_or_else(|| DeserializeError("Path capture value without a name".to_owned()))?;
+        seed.deserialize(capture)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value<'p, 'a> {
+    Segment(&'p Capture<'a>),
+    Segments(&'p [Capture<'a>]),
+    Missing,
+}
+
+/// A single capture, deserialized from its decoded value
+struct CaptureDeserializer<'p, 'a> {
+    name: &'p str,
+    value: Value<'p, 'a>,
+}
+
+impl<'p, 'a> CaptureDeserializer<'p, 'a> {
+    fn new(name: &'p str, value: &'p SegmentPatternValue<'a>) -> Self {
+        let value = match value {
+            SegmentPatternValue::Wildcard(capture) => Value::Segment(capture),
+            SegmentPatternValue::WildcardKleene(captures) => Value::Segments(captures),
+            SegmentPatternValue::Missing => Value::Missing,
+        };
+        Self { name, value }
+    }
+}
+
+impl<'p, 'a> ParseValue<'p> for CaptureDeserializer<'p, 'a> {
+    fn describe(&self) -> String {
+        format!("path capture `{}`", self.name)
+    }
+
+    fn value(&self) -> Result<&'p str, DeserializeError> {
+        match self.value {
+            Value::Segment(capture) => capture.decoded().map_err(|_| {
+                DeserializeError(format!("Path capture `{}` is not valid UTF-8", self.name))
+            }),
+            Value::Segments(_) => Err(DeserializeError(format!(
+                "Path capture `{}` spans several segments",
+                self.name
+            ))),
+            Value::Missing => Err(DeserializeError(format!(
+                "Path capture `{}` is missing",
+                self.name
+            ))),
+        }
+    }
+}
+
+impl<'de, 'p, 'a> de::Deserializer<'de> for CaptureDeserializer<'p, 'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Segment(_) => visitor.visit_str(self.value()?),
+            Value::Segments(_) => self.deserialize_seq(visitor),
+            Value::Missing => visitor.visit_none(),
+        }
+    }
+
+    deserialize_parsed!();
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.value()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Missing => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let segments = match self.value {
+            Value::Segments(segments) => segments,
+            _ => {
+                return Err(DeserializeError(format!(
+                    "Path capture `{}` is a single segment, not a sequence",
+                    self.name
+                )))
+            }
+        };
+        let name = self.name;
+        let captures = segments.iter().map(move |capture| CaptureDeserializer {
+            name,
+            value: Value::Segment(capture),
+        });
+        visitor.visit_seq(Captures::new(captures))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.value()?.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    fn deserialize<T: DeserializeOwned>(pattern: &str, path: &str) -> Result<T, DeserializeError> {
+        let pattern = UrlPattern::parse(pattern).unwrap();
+        let captures = pattern.match_str(path).unwrap();
+        pattern.deserialize_captures(&captures)
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Archive {
+        // Declared in a different order than the captures
+        month: Option<u8>,
+        year: u32,
+        user: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Format {
+        Json,
+        Html,
+    }
+
+    #[test]
+    fn captures_by_name() {
+        let archive: Archive = deserialize(
+            "/<user>/archive/<year:u32>/<month>?",
+            "/m%C3%A9/archive/2022",
+        )
+        .unwrap();
+        assert_eq!(
+            archive,
+            Archive {
+                month: None,
+                year: 2022,
+                user: "mé".to_owned(),
+            }
+        );
+
+        let map: HashMap<String, String> = deserialize("/<a>/<b>", "/x/y").unwrap();
+        assert_eq!(map["a"], "x");
+        assert_eq!(map["b"], "y");
+    }
+
+    #[test]
+    fn captures_by_position() {
+        let (name, format): (String, Format) =
+            deserialize("/<name>.<format>", "/report.json").unwrap();
+        assert_eq!((name.as_str(), format), ("report", Format::Json));
+
+        let (path, file): (Vec<String>, String) =
+            deserialize("/repos/<path..>/blob/<file>", "/repos/a/b/blob/c").unwrap();
+        assert_eq!(path, vec!["a", "b"]);
+        assert_eq!(file, "c");
+
+        let id: u64 = deserialize("/users/<id>", "/users/42").unwrap();
+        assert_eq!(id, 42);
+    }
+
+    #[test]
+    fn invalid_captures() {
+        let error =
+            deserialize::<Archive>("/<user>/archive/<year>", "/reels/archive/latest").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot parse path capture `year` with value `latest` as u32"
+        );
+
+        let error = deserialize::<(String, u32, u32)>("/<a>/<b>", "/x/1").unwrap_err();
+        assert_eq!(error.to_string(), "Expected 3 path captures, found 2");
+
+        let error = deserialize::<u32>("/<a>/<b>", "/1/2").unwrap_err();
+        assert_eq!(error.to_string(), "Expected a single path capture, found 2");
+
+        let error = deserialize::<Archive>("/<user>/<month>", "/reels/1").unwrap_err();
+        assert_eq!(error.to_string(), "missing field `year`");
+
+        let error = deserialize::<Format>("/<format>", "/xml").unwrap_err();
+        assert!(error.to_string().starts_with("unknown variant `xml`"));
+    }
+}
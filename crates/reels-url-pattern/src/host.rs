@@ -0,0 +1,168 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::de::{deserialize_captures, DeserializeError};
+use crate::{
+    parse_capture, Capture, Constraint, Ident, InvalidUrlPattern, PathCapture, SegmentPatternValue,
+};
+
+/// Host pattern for virtual hosting
+///
+/// - fixed host
+///   "api.example.com"
+/// - labels captured like url segments, optionally constrained
+///   "\<tenant\>.example.com", "\<region:[a-z]{2}\>.\<tenant\>.example.com"
+///
+/// Hosts are compared without their port and regardless of case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostPattern {
+    labels: Vec<HostLabel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum HostLabel {
+    Fixed(String),
+    Capture(Ident, Option<Constraint>),
+}
+
+impl HostPattern {
+    pub fn parse(s: &str) -> Result<Self, InvalidUrlPattern> {
+        let labels = s
+            .split('.')
+            .map(|label| match label.strip_prefix('<') {
+                Some(capture) => {
+                    let capture = capture
+                        .strip_suffix('>')
+                        .ok_or(InvalidUrlPattern::MissingClosingAngleBracket)?;
+                    let (ident, constraint) = parse_capture(capture)?;
+                    Ok(HostLabel::Capture(ident, constraint))
+                }
+                None if label.is_empty() || label.contains(['<', '>']) => {
+                    Err(InvalidUrlPattern::InvalidHostLabel)
+                }
+                None => Ok(HostLabel::Fixed(label.to_ascii_lowercase())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { labels })
+    }
+
+    /// Identifiers of the captures in the order they appear in the pattern
+    pub fn idents(&self) -> impl Iterator<Item = &Ident> {
+        self.labels.iter().filter_map(|label| match label {
+            HostLabel::Fixed(_) => None,
+            HostLabel::Capture(ident, _) => Some(ident),
+        })
+    }
+
+    /// Whether the pattern has no captures
+    pub fn is_fixed(&self) -> bool {
+        self.idents().next().is_none()
+    }
+
+    /// Match a host name, like the one of `Url::host_str`, and return the captured labels
+    pub fn match_host<'a>(&self, host: &'a str) -> Option<PathCapture<'a>> {
+        let labels: Vec<&str> = host.split('.').collect();
+        if labels.len() != self.labels.len() {
+            return None;
+        }
+        let mut values = Vec::new();
+        for (pattern, label) in self.labels.iter().zip(labels) {
+            match pattern {
+                HostLabel::Fixed(fixed) => {
+                    if !fixed.eq_ignore_ascii_case(label) {
+                        return None;
+                    }
+                }
+                HostLabel::Capture(_, constraint) => {
+                    let capture = Capture::new(label);
+                    if !capture.satisfies(constraint.as_ref()) {
+                        return None;
+                    }
+                    values.push(SegmentPatternValue::Wildcard(capture));
+                }
+            }
+        }
+        Some(values)
+    }
+
+    /// Deserialize the captures of a host matched by this pattern, see
+    /// `UrlPattern::deserialize_captures`
+    pub fn deserialize_captures<T: DeserializeOwned>(
+        &self,
+        captures: &[SegmentPatternValue],
+    ) -> Result<T, DeserializeError> {
+        let names: Vec<&str> = self.idents().map(Ident::as_str).collect();
+        deserialize_captures(&names, captures)
+    }
+}
+
+impl TryFrom<&str> for HostPattern {
+    type Error = InvalidUrlPattern;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        HostPattern::parse(value)
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, label) in self.labels.iter().enumerate() {
+            if index > 0 {
+                f.write_str(".")?;
+            }
+            match label {
+                HostLabel::Fixed(fixed) => f.write_str(fixed)?,
+                HostLabel::Capture(ident, None) => write!(f, "<{}>", ident)?,
+                HostLabel::Capture(ident, Some(constraint)) => {
+                    write!(f, "<{}:{}>", ident, constraint)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_patterns() {
+        let pat = HostPattern::parse("API.example.com").unwrap();
+        assert_eq!(pat.to_string(), "api.example.com");
+        assert!(pat.is_fixed());
+        assert!(pat.match_host("api.Example.com").is_some());
+        assert!(pat.match_host("www.example.com").is_none());
+        assert!(pat.match_host("example.com").is_none());
+
+        let pat = HostPattern::parse("<region:[a-z]{2}>.<tenant>.example.com").unwrap();
+        assert_eq!(pat.to_string(), "<region:[a-z]{2}>.<tenant>.example.com");
+        let values = pat.match_host("eu.acme.example.com").unwrap();
+        if let [SegmentPatternValue::Wildcard(region), SegmentPatternValue::Wildcard(tenant)] =
+            &values[..]
+        {
+            assert_eq!(*region, "eu");
+            assert_eq!(*tenant, "acme");
+        } else {
+            panic!("Invalid value");
+        }
+        let (region, tenant): (String, String) = pat.deserialize_captures(&values).unwrap();
+        assert_eq!((region.as_str(), tenant.as_str()), ("eu", "acme"));
+        assert!(pat.match_host("europe.acme.example.com").is_none());
+
+        assert!(matches!(
+            HostPattern::parse("www..com"),
+            Err(InvalidUrlPattern::InvalidHostLabel)
+        ));
+        assert!(matches!(
+            HostPattern::parse("v<version>.example.com"),
+            Err(InvalidUrlPattern::InvalidHostLabel)
+        ));
+        assert!(matches!(
+            HostPattern::parse("<tenant.example.com"),
+            Err(InvalidUrlPattern::MissingClosingAngleBracket)
+        ));
+    }
+}
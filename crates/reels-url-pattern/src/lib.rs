@@ -1,9 +1,25 @@
+use std::borrow::Cow;
+use std::str::Utf8Error;
 use std::{error, fmt};
 
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
 use serde::{Deserialize, Serialize};
 use unicode_xid::UnicodeXID;
-use url::Url;
-use urlencoding::encode;
+use url::{form_urlencoded, Url};
+
+mod constraint;
+mod de;
+mod host;
+mod overlap;
+mod query;
+mod tree;
+
+pub use constraint::{Constraint, SegmentRegex};
+pub use de::DeserializeError;
+pub use host::HostPattern;
+pub use overlap::Overlap;
+pub use query::deserialize_query;
+pub use tree::PatternTree;
 
 #[derive(Debug)]
 pub enum InvalidUrlPattern {
@@ -11,6 +27,11 @@ pub enum InvalidUrlPattern {
     InvalidIdentifier,
     MissingClosingAngleBracket,
     InvalidKleenePosition,
+    InvalidConstraint,
+    AdjacentCaptures,
+    InvalidOptionalPosition,
+    InvalidQueryParameter,
+    InvalidHostLabel,
 }
 
 impl fmt::Display for InvalidUrlPattern {
@@ -25,8 +46,24 @@ impl fmt::Display for InvalidUrlPattern {
             InvalidUrlPattern::MissingClosingAngleBracket => {
                 f.write_str("Url pattern is missing closing `>` for capture")
             }
-            InvalidUrlPattern::InvalidKleenePosition => {
-                f.write_str("Wildcard kleene capture can only be the last segment pattern")
+            InvalidUrlPattern::InvalidKleenePosition => f.write_str(
+                "Wildcard kleene capture must be a whole segment, appear once and not be \
+                     followed by optional captures",
+            ),
+            InvalidUrlPattern::InvalidConstraint => {
+                f.write_str("Url capture constraint is not a known type or a valid regex")
+            }
+            InvalidUrlPattern::AdjacentCaptures => {
+                f.write_str("Url captures within a segment must be separated by a literal")
+            }
+            InvalidUrlPattern::InvalidOptionalPosition => {
+                f.write_str("Optional captures can only be followed by other optional captures")
+            }
+            InvalidUrlPattern::InvalidQueryParameter => {
+                f.write_str("Required query parameters must have non-empty names")
+            }
+            InvalidUrlPattern::InvalidHostLabel => {
+                f.write_str("Host pattern labels must be a non-empty name or a single capture")
             }
         }
     }
@@ -34,6 +71,34 @@ impl fmt::Display for InvalidUrlPattern {
 
 impl error::Error for InvalidUrlPattern {}
 
+/// Error filling in a url pattern with `UrlPattern::format`
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// No value for the capture with this name
+    MissingParameter(String),
+    /// The value does not meet the constraint of the capture with this name
+    InvalidParameter(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::MissingParameter(name) => {
+                write!(f, "Missing value for url capture `{}`", name)
+            }
+            FormatError::InvalidParameter(name) => {
+                write!(
+                    f,
+                    "Value for url capture `{}` does not meet its constraint",
+                    name
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for FormatError {}
+
 pub type PathCapture<'a> = Vec<SegmentPatternValue<'a>>;
 
 /// Url Pattern for routing
@@ -42,51 +107,303 @@ pub type PathCapture<'a> = Vec<SegmentPatternValue<'a>>;
 ///   "/a/b/c"
 /// - dynamic path with capture
 ///   "/a/\<name\>/c"
-/// - capture rest of the path
-///   "/a/b/\<rest..\>"
+/// - dynamic path with typed or regex constrained capture
+///   "/a/\<id:u32\>/c", "/a/\<slug:[a-z0-9-]+\>/c"
+/// - captures mixed with literals within a segment
+///   "/a/\<name\>.\<ext\>", "/a/v\<version:u32\>"
+/// - optional trailing captures
+///   "/a/\<page\>?", "/a/\<year:u32\>?/\<month:u32\>?"
+/// - capture any number of segments, once per pattern
+///   "/a/b/\<rest..\>", "/repos/\<path..\>/blob/\<file\>"
+/// - query parameters the url must have, with any value
+///   "/search?q", "/search?q&lang", "/docs/\<page\>??lang"
+///
+/// A kleene capture takes the segments left over by the segment patterns after it, so
+/// "/repos/a/blob/b/blob/c" captures "a/blob/b" as the path of the second example.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UrlPattern {
     pattern: Vec<SegmentPattern>,
+    /// Names of the required query parameters
+    query: Vec<String>,
 }
 
 impl UrlPattern {
     pub fn parse(s: &str) -> Result<Self, InvalidUrlPattern> {
+        let (s, query) = split_query(s);
+        let query = match query {
+            Some(query) => query.split('&').map(str::to_owned).collect(),
+            None => Vec::new(),
+        };
+        if query.iter().any(String::is_empty) {
+            return Err(InvalidUrlPattern::InvalidQueryParameter);
+        }
+
         let mut segments = s.split('/');
         (segments.next() == Some(""))
             .then_some(0)
             .ok_or(InvalidUrlPattern::MissingForwardSlash)?;
 
-        let mut patterns = Vec::new();
-        while let Some(segment) = segments.next() {
+        let mut patterns: Vec<SegmentPattern> = Vec::new();
+        let mut kleene = false;
+        for segment in segments {
             let seg = SegmentPattern::parse(segment)?;
-            if matches!(seg, SegmentPattern::WildcardKleene(_)) && segments.next().is_some() {
-                // make sure at most one WildcardKleene exist and it is the last pattern
+            if kleene
+                && matches!(
+                    seg,
+                    SegmentPattern::WildcardKleene(_) | SegmentPattern::Optional(_, _)
+                )
+            {
+                // the segments after a kleene capture have to match one path segment each
                 return Err(InvalidUrlPattern::InvalidKleenePosition);
+            } else if matches!(patterns.last(), Some(SegmentPattern::Optional(_, _)))
+                && !matches!(seg, SegmentPattern::Optional(_, _))
+            {
+                // optional captures can only be trailing
+                return Err(InvalidUrlPattern::InvalidOptionalPosition);
             } else {
+                kleene |= matches!(seg, SegmentPattern::WildcardKleene(_));
                 patterns.push(seg);
             }
         }
 
-        Ok(UrlPattern { pattern: patterns })
+        Ok(UrlPattern {
+            pattern: patterns,
+            query,
+        })
+    }
+
+    /// Identifiers of the captures in the order they appear in the pattern
+    pub fn idents(&self) -> impl Iterator<Item = &Ident> {
+        self.pattern.iter().flat_map(|segment| match segment {
+            SegmentPattern::Fixed(_) => Vec::new(),
+            SegmentPattern::Wildcard(ident)
+            | SegmentPattern::TypedWildcard(ident, _)
+            | SegmentPattern::Optional(ident, _)
+            | SegmentPattern::WildcardKleene(ident) => vec![ident],
+            SegmentPattern::Compound(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    SegmentPart::Literal(_) => None,
+                    SegmentPart::Capture(ident, _) => Some(ident),
+                })
+                .collect(),
+        })
+    }
+
+    /// Fill in the captures with the values of the parameters of the same name
+    ///
+    /// Values are percent-encoded and checked against the constraints of their captures.
+    /// Optional captures without a value are left out along with the segment, the value of a
+    /// kleene capture is split into segments on "/". Parameters that are not captured by the
+    /// pattern are ignored.
+    ///
+    /// Examples
+    /// ```
+    /// # use reels_url_pattern::UrlPattern;
+    /// let pattern = UrlPattern::parse("/users/<id:u32>/<tab>?").unwrap();
+    /// assert_eq!(pattern.format(&[("id", &42)]).unwrap(), "/users/42");
+    /// assert_eq!(
+    ///     pattern.format(&[("id", &42), ("tab", &"pinned posts")]).unwrap(),
+    ///     "/users/42/pinned%20posts"
+    /// );
+    /// assert!(pattern.format(&[("id", &"me")]).is_err());
+    /// ```
+    pub fn format(&self, params: &[(&str, &dyn fmt::Display)]) -> Result<String, FormatError> {
+        let param = |ident: &Ident| {
+            params
+                .iter()
+                .find(|(key, _)| *key == ident.as_str())
+                .map(|(_, value)| value.to_string())
+        };
+        let required = |ident: &Ident, constraint: Option<&Constraint>| {
+            let value =
+                param(ident).ok_or_else(|| FormatError::MissingParameter(ident.to_string()))?;
+            if constraint.is_some_and(|constraint| !constraint.matches(&value)) {
+                return Err(FormatError::InvalidParameter(ident.to_string()));
+            }
+            Ok(encode_segment(&value))
+        };
+
+        let mut url = String::new();
+        let mut missing: Option<&Ident> = None;
+        for segment in &self.pattern {
+            if let SegmentPattern::Optional(ident, constraint) = segment {
+                match (param(ident), missing) {
+                    (None, _) => missing = missing.or(Some(ident)),
+                    (Some(_), Some(missing)) => {
+                        return Err(FormatError::MissingParameter(missing.to_string()))
+                    }
+                    (Some(_), None) => {
+                        url.push('/');
+                        url.push_str(&required(ident, constraint.as_ref())?);
+                    }
+                }
+                continue;
+            }
+
+            url.push('/');
+            match segment {
+                SegmentPattern::Fixed(s) => url.push_str(s),
+                SegmentPattern::Wildcard(ident) => url.push_str(&required(ident, None)?),
+                SegmentPattern::TypedWildcard(ident, constraint) => {
+                    url.push_str(&required(ident, Some(constraint))?)
+                }
+                SegmentPattern::Compound(parts) => {
+                    for part in parts {
+                        match part {
+                            SegmentPart::Literal(s) => url.push_str(s),
+                            SegmentPart::Capture(ident, constraint) => {
+                                url.push_str(&required(ident, constraint.as_ref())?)
+                            }
+                        }
+                    }
+                }
+                SegmentPattern::WildcardKleene(ident) => {
+                    let value = param(ident)
+                        .ok_or_else(|| FormatError::MissingParameter(ident.to_string()))?;
+                    let segments: Vec<String> = value.split('/').map(encode_segment).collect();
+                    url.push_str(&segments.join("/"));
+                }
+                SegmentPattern::Optional(_, _) => {
+                    unreachable!("optional captures are handled above")
+                }
+            }
+        }
+        if url.is_empty() {
+            url.push('/');
+        }
+        if !self.query.is_empty() {
+            let mut query = form_urlencoded::Serializer::new(String::new());
+            for key in &self.query {
+                let value = params
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .ok_or_else(|| FormatError::MissingParameter(key.clone()))?;
+                query.append_pair(key, &value.1.to_string());
+            }
+            url.push('?');
+            url.push_str(&query.finish());
+        }
+        Ok(url)
+    }
+
+    /// Segment patterns in the order they appear in the pattern
+    pub fn segments(&self) -> &[SegmentPattern] {
+        &self.pattern
+    }
+
+    /// Names of the query parameters the url must have
+    pub fn query(&self) -> &[String] {
+        &self.query
+    }
+
+    /// Concatenate two patterns, used for mounting `other` under the prefix `self`
+    ///
+    /// The root pattern "/" is the identity, so "/api" joined with "/" is "/api". The
+    /// joined pattern requires the query parameters of both.
+    pub fn join(&self, other: &UrlPattern) -> Result<UrlPattern, InvalidUrlPattern> {
+        let mut joined = self.join_path(other)?;
+        for key in self.query.iter().chain(&other.query) {
+            if !joined.query.contains(key) {
+                joined.query.push(key.clone());
+            }
+        }
+        Ok(joined)
+    }
+
+    fn join_path(&self, other: &UrlPattern) -> Result<UrlPattern, InvalidUrlPattern> {
+        if self.is_root() {
+            return Ok(other.clone());
+        }
+        if self
+            .pattern
+            .iter()
+            .any(|segment| matches!(segment, SegmentPattern::WildcardKleene(_)))
+        {
+            return Err(InvalidUrlPattern::InvalidKleenePosition);
+        }
+        if self
+            .pattern
+            .iter()
+            .any(|segment| matches!(segment, SegmentPattern::Optional(_, _)))
+        {
+            return Err(InvalidUrlPattern::InvalidOptionalPosition);
+        }
+        if other.is_root() {
+            return Ok(self.clone());
+        }
+        let pattern = self.pattern.iter().chain(&other.pattern).cloned().collect();
+        Ok(UrlPattern {
+            pattern,
+            query: Vec::new(),
+        })
+    }
+
+    fn is_root(&self) -> bool {
+        matches!(&self.pattern[..], [SegmentPattern::Fixed(s)] if s.is_empty())
     }
 
     pub fn match_url<'a>(&self, url: &'a Url) -> Option<Vec<SegmentPatternValue<'a>>> {
+        if !self.matches_query(url.query()) {
+            return None;
+        }
         let segments = url.path_segments()?;
-        self.match_iter(segments)
+        self.match_iter(segments, false)
     }
 
+    /// Same as `match_url` for a path starting with "/", optionally followed by a query
     pub fn match_str<'a>(&self, path: &'a str) -> Option<Vec<SegmentPatternValue<'a>>> {
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path, None),
+        };
+        if !self.matches_query(query) {
+            return None;
+        }
         let segments = path.split('/').skip(1);
-        self.match_iter(segments)
+        self.match_iter(segments, false)
+    }
+
+    /// Whether the query string has every required query parameter
+    pub fn matches_query(&self, query: Option<&str>) -> bool {
+        let keys = query_keys(query);
+        self.query
+            .iter()
+            .all(|key| keys.iter().any(|k| k == key.as_str()))
     }
 
-    fn match_iter<'a, I>(&self, mut segments: I) -> Option<Vec<SegmentPatternValue<'a>>>
+    /// Match the leading segments of the url path and ignore the rest
+    ///
+    /// The root pattern "/" is a prefix of every path.
+    pub fn match_url_prefix<'a>(&self, url: &'a Url) -> Option<Vec<SegmentPatternValue<'a>>> {
+        if !self.matches_query(url.query()) {
+            return None;
+        }
+        if self.is_root() {
+            return Some(Vec::new());
+        }
+        let segments = url.path_segments()?;
+        self.match_iter(segments, true)
+    }
+
+    /// Number of segments in the pattern
+    pub fn len(&self) -> usize {
+        self.pattern.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+
+    fn match_iter<'a, I>(&self, segments: I, prefix: bool) -> Option<Vec<SegmentPatternValue<'a>>>
     where
         I: Iterator<Item = &'a str>,
     {
-        let patterns = self.pattern.iter();
+        let segments: Vec<&'a str> = segments.collect();
+        let mut segments = segments.into_iter();
         let mut matched_values = Vec::new();
-        for pattern in patterns {
+        let mut missing = false;
+        for (index, pattern) in self.pattern.iter().enumerate() {
             match pattern {
                 SegmentPattern::Fixed(pat) => {
                     let segment = segments.next()?;
@@ -97,16 +414,44 @@ impl UrlPattern {
                 }
                 SegmentPattern::Wildcard(_) => {
                     let segment = segments.next()?;
-                    matched_values.push(SegmentPatternValue::Wildcard(segment));
+                    matched_values.push(SegmentPatternValue::Wildcard(Capture::new(segment)));
                 }
+                SegmentPattern::TypedWildcard(_, constraint) => {
+                    let capture = Capture::new(segments.next()?);
+                    if !capture.satisfies(Some(constraint)) {
+                        return None;
+                    }
+                    matched_values.push(SegmentPatternValue::Wildcard(capture));
+                }
+                SegmentPattern::Compound(parts) => {
+                    let segment = segments.next()?;
+                    if !match_parts(parts, segment, &mut matched_values) {
+                        return None;
+                    }
+                }
+                SegmentPattern::Optional(_, constraint) => match segments.next() {
+                    // An empty segment from a trailing slash counts as missing
+                    None | Some("") => {
+                        missing = true;
+                        matched_values.push(SegmentPatternValue::Missing);
+                    }
+                    Some(segment) => {
+                        let capture = Capture::new(segment);
+                        if missing || !capture.satisfies(constraint.as_ref()) {
+                            return None;
+                        }
+                        matched_values.push(SegmentPatternValue::Wildcard(capture));
+                    }
+                },
                 SegmentPattern::WildcardKleene(_) => {
-                    let segments = segments.collect();
-                    matched_values.push(SegmentPatternValue::WildcardKleene(segments));
-                    return Some(matched_values);
+                    // The patterns after a kleene capture match one segment each
+                    let taken = segments.len().checked_sub(self.pattern.len() - index - 1)?;
+                    let values = segments.by_ref().take(taken).map(Capture::new).collect();
+                    matched_values.push(SegmentPatternValue::WildcardKleene(values));
                 }
             }
         }
-        if segments.next().is_some() {
+        if !prefix && segments.next().is_some() {
             // have additional segments not matched in pattern
             // eprintln!("more segments than pattern");
             None
@@ -137,55 +482,330 @@ impl fmt::Display for UrlPattern {
         for segment in &self.pattern {
             write!(f, "/{}", segment)?
         }
+        if !self.query.is_empty() {
+            write!(f, "?{}", self.query.join("&"))?
+        }
 
         Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SegmentPatternValue<'a> {
-    Wildcard(&'a str),
-    WildcardKleene(Vec<&'a str>),
+    Wildcard(Capture<'a>),
+    WildcardKleene(Vec<Capture<'a>>),
+    /// Optional capture absent from the path
+    Missing,
+}
+
+/// Captured path segment, percent-decoded with the raw form kept around
+///
+/// Segments that do not decode to valid UTF-8 still match wildcards without a constraint,
+/// `decoded` reports them as an error instead of passing the raw value on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture<'a> {
+    raw: &'a str,
+    decoded: Result<Cow<'a, str>, Utf8Error>,
+}
+
+impl<'a> Capture<'a> {
+    pub fn new(raw: &'a str) -> Self {
+        Self {
+            raw,
+            decoded: percent_decode_str(raw).decode_utf8(),
+        }
+    }
+
+    /// The segment as it appears in the url
+    pub fn raw(&self) -> &'a str {
+        self.raw
+    }
+
+    /// The percent-decoded segment
+    pub fn decoded(&self) -> Result<&str, Utf8Error> {
+        self.decoded.as_deref().map_err(|e| *e)
+    }
+
+    pub fn into_decoded(self) -> Result<Cow<'a, str>, Utf8Error> {
+        self.decoded
+    }
+
+    /// Whether the decoded segment meets the constraint, if any
+    fn satisfies(&self, constraint: Option<&Constraint>) -> bool {
+        match (constraint, self.decoded()) {
+            (None, _) => true,
+            (Some(constraint), Ok(value)) => constraint.matches(value),
+            (Some(_), Err(_)) => false,
+        }
+    }
+}
+
+impl fmt::Display for Capture<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.decoded().unwrap_or(self.raw))
+    }
+}
+
+impl PartialEq<str> for Capture<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.decoded() == Ok(other)
+    }
+}
+
+impl PartialEq<&str> for Capture<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<String> for Capture<'_> {
+    fn eq(&self, other: &String) -> bool {
+        self == other.as_str()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SegmentPattern {
     Fixed(String),
     Wildcard(Ident),
+    TypedWildcard(Ident, Constraint),
+    /// Capture that may be left out, only followed by other optional captures
+    Optional(Ident, Option<Constraint>),
     WildcardKleene(Ident),
+    /// Literals and captures within one segment, no two captures are adjacent
+    Compound(Vec<SegmentPart>),
 }
 
 impl SegmentPattern {
     fn parse(segment: &str) -> Result<Self, InvalidUrlPattern> {
-        // fixed: start with not "<"
+        // fixed: without "<"
         // Wildcard: start with "<", valid ident in the middle, closing with ">"
+        // TypedWildcard: like Wildcard with ":" and a type name or regex after the ident
         // WildcardMulti: start with "<", valid ident in the middle, closing with "..>"
-        if segment.starts_with('<') {
-            let n = segment.len();
-            if segment.ends_with("..>") {
-                let ident = Ident::parse(&segment[1..n - 3])
-                    .map_err(|_| InvalidUrlPattern::InvalidIdentifier)?;
-                Ok(SegmentPattern::WildcardKleene(ident))
-            } else if segment.ends_with('>') {
-                let ident = Ident::parse(&segment[1..n - 1])
-                    .map_err(|_| InvalidUrlPattern::InvalidIdentifier)?;
-                Ok(SegmentPattern::Wildcard(ident))
-            } else {
-                Err(InvalidUrlPattern::MissingClosingAngleBracket)
+        // Optional: Wildcard or TypedWildcard followed by "?"
+        // Compound: any other segment with "<", captures close at the first ">"
+        if !segment.contains('<') {
+            return Ok(SegmentPattern::Fixed(encode_literal(segment)));
+        }
+        if let Some(capture) = segment.strip_suffix(">?") {
+            if capture.starts_with('<') && !capture.contains('>') {
+                let (ident, constraint) = parse_capture(&capture[1..])?;
+                return Ok(SegmentPattern::Optional(ident, constraint));
+            }
+        }
+        let n = segment.len();
+        if !segment.starts_with('<') || segment.find('>') != Some(n - 1) {
+            return Self::parse_compound(segment).map(SegmentPattern::Compound);
+        }
+
+        let capture = &segment[1..n - 1];
+        if !capture.contains(':') {
+            if let Some(ident) = capture.strip_suffix("..") {
+                let ident =
+                    Ident::parse(ident).map_err(|_| InvalidUrlPattern::InvalidIdentifier)?;
+                return Ok(SegmentPattern::WildcardKleene(ident));
+            }
+        }
+        match parse_capture(capture)? {
+            (ident, Some(constraint)) => Ok(SegmentPattern::TypedWildcard(ident, constraint)),
+            (ident, None) => Ok(SegmentPattern::Wildcard(ident)),
+        }
+    }
+
+    fn parse_compound(mut segment: &str) -> Result<Vec<SegmentPart>, InvalidUrlPattern> {
+        let mut parts = Vec::new();
+        while !segment.is_empty() {
+            match segment.find('<') {
+                Some(0) => {
+                    let end = segment
+                        .find('>')
+                        .ok_or(InvalidUrlPattern::MissingClosingAngleBracket)?;
+                    if matches!(parts.last(), Some(SegmentPart::Capture(_, _))) {
+                        return Err(InvalidUrlPattern::AdjacentCaptures);
+                    }
+                    let capture = &segment[1..end];
+                    if capture.ends_with("..") && !capture.contains(':') {
+                        return Err(InvalidUrlPattern::InvalidKleenePosition);
+                    }
+                    let (ident, constraint) = parse_capture(capture)?;
+                    parts.push(SegmentPart::Capture(ident, constraint));
+                    segment = &segment[end + 1..];
+                }
+                Some(start) => {
+                    parts.push(SegmentPart::Literal(encode_literal(&segment[..start])));
+                    segment = &segment[start..];
+                }
+                None => {
+                    parts.push(SegmentPart::Literal(encode_literal(segment)));
+                    segment = "";
+                }
+            }
+        }
+        Ok(parts)
+    }
+}
+
+/// Parse the inside of a capture, an ident optionally followed by ":" and its constraint
+fn parse_capture(capture: &str) -> Result<(Ident, Option<Constraint>), InvalidUrlPattern> {
+    let (ident, constraint) = match capture.split_once(':') {
+        Some((ident, constraint)) => (ident, Some(constraint)),
+        None => (capture, None),
+    };
+    let ident = Ident::parse(ident).map_err(|_| InvalidUrlPattern::InvalidIdentifier)?;
+    let constraint = constraint
+        .map(Constraint::parse)
+        .transpose()
+        .map_err(|_| InvalidUrlPattern::InvalidConstraint)?;
+    Ok((ident, constraint))
+}
+
+/// Split off the required query parameters of a pattern
+///
+/// A '?' right after a capture marks it optional, the query starts at the next '?' outside
+/// of the captures.
+fn split_query(s: &str) -> (&str, Option<&str>) {
+    let mut depth = 0usize;
+    let mut previous = None;
+    for (index, c) in s.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            '?' if depth == 0 && previous != Some('>') => {
+                return (&s[..index], Some(&s[index + 1..]));
+            }
+            _ => {}
+        }
+        previous = Some(c);
+    }
+    (s, None)
+}
+
+/// Names of the parameters of a query string, with the "[]" suffix of arrays removed
+pub(crate) fn query_keys(query: Option<&str>) -> Vec<Cow<'_, str>> {
+    let query = query.unwrap_or_default();
+    form_urlencoded::parse(query.as_bytes())
+        .map(|(key, _)| match key {
+            Cow::Borrowed(key) => Cow::Borrowed(key.strip_suffix("[]").unwrap_or(key)),
+            Cow::Owned(key) => match key.strip_suffix("[]") {
+                Some(key) => Cow::Owned(key.to_owned()),
+                None => Cow::Owned(key),
+            },
+        })
+        .collect()
+}
+
+/// Characters the url parser percent-encodes in paths
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Characters percent-encoded in capture values, which may not contain path delimiters
+const SEGMENT: &AsciiSet = &PATH.add(b'/').add(b'%');
+
+/// Encode a literal the same way it appears in a parsed url path
+fn encode_literal(s: &str) -> String {
+    utf8_percent_encode(s, PATH).to_string()
+}
+
+/// Encode a value filled into a capture
+fn encode_segment(s: &str) -> String {
+    utf8_percent_encode(s, SEGMENT).to_string()
+}
+
+/// Part of a compound segment pattern
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SegmentPart {
+    Literal(String),
+    Capture(Ident, Option<Constraint>),
+}
+
+impl fmt::Display for SegmentPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SegmentPart::Literal(s) => f.write_str(s),
+            SegmentPart::Capture(ident, None) => write!(f, "<{}>", ident),
+            SegmentPart::Capture(ident, Some(constraint)) => {
+                write!(f, "<{}:{}>", ident, constraint)
             }
-        } else {
-            let s = encode(segment).to_string();
-            Ok(SegmentPattern::Fixed(s))
         }
     }
 }
 
+/// Match a segment against the parts of a compound segment pattern
+///
+/// Captures are non-empty and greedy: each one takes the longest value that still lets the
+/// rest of the segment match, so "\<name\>.\<ext\>" splits "a.tar.gz" into "a.tar" and
+/// "gz". Values are pushed onto `values` only when the whole segment matches.
+pub(crate) fn match_parts<'a>(
+    parts: &[SegmentPart],
+    segment: &'a str,
+    values: &mut Vec<SegmentPatternValue<'a>>,
+) -> bool {
+    let len = values.len();
+    let matched = match parts {
+        [] => segment.is_empty(),
+        [SegmentPart::Literal(literal), rest @ ..] => {
+            match segment.strip_prefix(literal.as_str()) {
+                Some(segment) => match_parts(rest, segment, values),
+                None => false,
+            }
+        }
+        [SegmentPart::Capture(_, constraint), rest @ ..] => {
+            let accepts =
+                |value: &Capture| !value.raw().is_empty() && value.satisfies(constraint.as_ref());
+            match rest.first() {
+                None => {
+                    let value = Capture::new(segment);
+                    let matched = accepts(&value);
+                    if matched {
+                        values.push(SegmentPatternValue::Wildcard(value));
+                    }
+                    matched
+                }
+                // Captures are always followed by a literal, try its occurrences from the
+                // last one backwards
+                Some(SegmentPart::Literal(literal)) => segment
+                    .rmatch_indices(literal.as_str())
+                    .map(|(end, _)| end)
+                    .any(|end| {
+                        let value = Capture::new(&segment[..end]);
+                        if !accepts(&value) {
+                            return false;
+                        }
+                        values.push(SegmentPatternValue::Wildcard(value));
+                        if match_parts(rest, &segment[end..], values) {
+                            return true;
+                        }
+                        values.truncate(len);
+                        false
+                    }),
+                Some(SegmentPart::Capture(_, _)) => unreachable!("captures are never adjacent"),
+            }
+        }
+    };
+    if !matched {
+        values.truncate(len);
+    }
+    matched
+}
+
 impl fmt::Display for SegmentPattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SegmentPattern::Fixed(s) => f.write_str(s),
             SegmentPattern::Wildcard(s) => write!(f, "<{}>", s),
+            SegmentPattern::TypedWildcard(s, constraint) => write!(f, "<{}:{}>", s, constraint),
+            SegmentPattern::Optional(s, None) => write!(f, "<{}>?", s),
+            SegmentPattern::Optional(s, Some(constraint)) => write!(f, "<{}:{}>?", s, constraint),
             SegmentPattern::WildcardKleene(s) => write!(f, "<{}..>", s),
+            SegmentPattern::Compound(parts) => parts.iter().try_for_each(|part| part.fmt(f)),
         }
     }
 }
@@ -206,6 +826,10 @@ impl Ident {
             Err(())
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 impl fmt::Display for Ident {
@@ -224,6 +848,48 @@ mod tests {
         assert_eq!(pat.to_string(), "/a/<b>/<c..>");
     }
 
+    #[test]
+    fn capture_idents() {
+        let pat: UrlPattern = "/a/<b>/c/<d..>".try_into().unwrap();
+        let idents: Vec<&str> = pat.idents().map(Ident::as_str).collect();
+        assert_eq!(idents, vec!["b", "d"]);
+    }
+
+    #[test]
+    fn join_patterns() {
+        let prefix: UrlPattern = "/api/<version>".try_into().unwrap();
+        let pat: UrlPattern = "/users/<id>".try_into().unwrap();
+        assert_eq!(
+            prefix.join(&pat).unwrap().to_string(),
+            "/api/<version>/users/<id>"
+        );
+
+        let root: UrlPattern = "/".try_into().unwrap();
+        assert_eq!(prefix.join(&root).unwrap().to_string(), "/api/<version>");
+        assert_eq!(root.join(&pat).unwrap().to_string(), "/users/<id>");
+
+        let kleene: UrlPattern = "/<rest..>".try_into().unwrap();
+        assert!(kleene.join(&pat).is_err());
+    }
+
+    #[test]
+    fn prefix_patterns() {
+        let prefix: UrlPattern = "/api/<version>".try_into().unwrap();
+        let url = Url::parse("http://localhost/api/v1/users").unwrap();
+        let values = prefix.match_url_prefix(&url).unwrap();
+        if let [SegmentPatternValue::Wildcard(version)] = &values[..] {
+            assert_eq!(*version, "v1");
+        } else {
+            panic!("Invalid value");
+        }
+
+        let url = Url::parse("http://localhost/apis/v1").unwrap();
+        assert!(prefix.match_url_prefix(&url).is_none());
+
+        let root: UrlPattern = "/".try_into().unwrap();
+        assert!(root.match_url_prefix(&url).is_some());
+    }
+
     #[test]
     fn parse_root() {
         let pat: UrlPattern = "/".try_into().unwrap();
@@ -285,6 +951,244 @@ mod tests {
         }
     }
 
+    #[test]
+    fn typed_patterns() {
+        let pat: UrlPattern = "/users/<id:u32>".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/users/<id:u32>");
+        assert!(pat.match_str("/users/42").is_some());
+        assert!(pat.match_str("/users/-1").is_none());
+        assert!(pat.match_str("/users/me").is_none());
+
+        let pat: UrlPattern = "/files/<name:uuid>".try_into().unwrap();
+        assert!(pat
+            .match_str("/files/67e55044-10b1-426f-9247-bb680e5fe0c8")
+            .is_some());
+        assert!(pat.match_str("/files/67e55044-10b1-426f-9247").is_none());
+
+        let pat: UrlPattern = "/posts/<slug:[a-z0-9-]+>".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/posts/<slug:[a-z0-9-]+>");
+        let values = pat.match_str("/posts/hello-world").unwrap();
+        if let [SegmentPatternValue::Wildcard(slug)] = &values[..] {
+            assert_eq!(*slug, "hello-world");
+        } else {
+            panic!("Invalid value");
+        }
+        // The regex has to match the whole segment
+        assert!(pat.match_str("/posts/Hello-world").is_none());
+
+        let idents: Vec<&str> = pat.idents().map(Ident::as_str).collect();
+        assert_eq!(idents, vec!["slug"]);
+
+        assert!(matches!(
+            UrlPattern::parse("/posts/<slug:[a-z>"),
+            Err(InvalidUrlPattern::InvalidConstraint)
+        ));
+        assert!(matches!(
+            UrlPattern::parse("/posts/<:u32>"),
+            Err(InvalidUrlPattern::InvalidIdentifier)
+        ));
+    }
+
+    #[test]
+    fn compound_patterns() {
+        let pat: UrlPattern = "/files/<name>.<ext>".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/files/<name>.<ext>");
+        let values = pat.match_str("/files/archive.tar.gz").unwrap();
+        if let [SegmentPatternValue::Wildcard(name), SegmentPatternValue::Wildcard(ext)] =
+            &values[..]
+        {
+            assert_eq!(*name, "archive.tar");
+            assert_eq!(*ext, "gz");
+        } else {
+            panic!("Invalid value");
+        }
+        assert!(pat.match_str("/files/readme").is_none());
+        assert!(pat.match_str("/files/.gitignore").is_none());
+
+        let pat: UrlPattern = "/api/v<version:u32>".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/api/v<version:u32>");
+        let values = pat.match_str("/api/v2").unwrap();
+        if let [SegmentPatternValue::Wildcard(version)] = &values[..] {
+            assert_eq!(*version, "2");
+        } else {
+            panic!("Invalid value");
+        }
+        assert!(pat.match_str("/api/vx").is_none());
+
+        // Backtrack when the greedy split leaves a value the constraint rejects
+        let pat: UrlPattern = "/<a>-<b:u32>".try_into().unwrap();
+        let values = pat.match_str("/x-1-y").map(|values| values.len());
+        assert_eq!(values, None);
+        let values = pat.match_str("/x-y-1").unwrap();
+        if let [SegmentPatternValue::Wildcard(a), SegmentPatternValue::Wildcard(b)] = &values[..] {
+            assert_eq!((a.raw(), b.raw()), ("x-y", "1"));
+        } else {
+            panic!("Invalid value");
+        }
+
+        let pat: UrlPattern = "/@<user>".try_into().unwrap();
+        assert!(pat.match_str("/@reels").is_some());
+
+        let pat: UrlPattern = "/<a>/<b>.<c>".try_into().unwrap();
+        let idents: Vec<&str> = pat.idents().map(Ident::as_str).collect();
+        assert_eq!(idents, vec!["a", "b", "c"]);
+
+        assert!(matches!(
+            UrlPattern::parse("/<a><b>"),
+            Err(InvalidUrlPattern::AdjacentCaptures)
+        ));
+        assert!(matches!(
+            UrlPattern::parse("/<a>.<b"),
+            Err(InvalidUrlPattern::MissingClosingAngleBracket)
+        ));
+        assert!(matches!(
+            UrlPattern::parse("/v<rest..>"),
+            Err(InvalidUrlPattern::InvalidKleenePosition)
+        ));
+    }
+
+    #[test]
+    fn query_patterns() {
+        let pat: UrlPattern = "/search?q&lang".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/search?q&lang");
+        assert_eq!(pat.query(), ["q", "lang"]);
+        assert!(pat.match_str("/search?lang=en&q=").is_some());
+        assert!(pat.match_str("/search?q=rust").is_none());
+        assert!(pat.match_str("/search").is_none());
+        let url = Url::parse("http://localhost/search?q=rust&lang[]=en").unwrap();
+        assert!(pat.match_url(&url).is_some());
+        assert_eq!(
+            pat.format(&[("q", &"rust lang"), ("lang", &"en")]).unwrap(),
+            "/search?q=rust+lang&lang=en"
+        );
+        assert_eq!(
+            pat.format(&[("q", &"rust")]),
+            Err(FormatError::MissingParameter("lang".to_owned()))
+        );
+
+        let pat: UrlPattern = "/docs/<page>??lang".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/docs/<page>??lang");
+        let values = pat.match_str("/docs?lang=en").unwrap();
+        assert!(matches!(&values[..], [SegmentPatternValue::Missing]));
+
+        let prefix: UrlPattern = "/api?key".try_into().unwrap();
+        let joined = prefix.join(&pat).unwrap();
+        assert_eq!(joined.to_string(), "/api/docs/<page>??key&lang");
+
+        assert!(matches!(
+            UrlPattern::parse("/search?"),
+            Err(InvalidUrlPattern::InvalidQueryParameter)
+        ));
+        assert!(matches!(
+            UrlPattern::parse("/search?q&&lang"),
+            Err(InvalidUrlPattern::InvalidQueryParameter)
+        ));
+    }
+
+    #[test]
+    fn optional_patterns() {
+        let pat: UrlPattern = "/docs/<page>?".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/docs/<page>?");
+        let values = pat.match_str("/docs/intro").unwrap();
+        if let [SegmentPatternValue::Wildcard(page)] = &values[..] {
+            assert_eq!(*page, "intro");
+        } else {
+            panic!("Invalid value");
+        }
+        for path in ["/docs", "/docs/"] {
+            let values = pat.match_str(path).unwrap();
+            assert!(matches!(&values[..], [SegmentPatternValue::Missing]));
+        }
+        assert!(pat.match_str("/docs/intro/more").is_none());
+
+        let pat: UrlPattern = "/archive/<year:u32>?/<month:u32>?".try_into().unwrap();
+        let values = pat.match_str("/archive/2022").unwrap();
+        if let [SegmentPatternValue::Wildcard(year), SegmentPatternValue::Missing] = &values[..] {
+            assert_eq!(*year, "2022");
+        } else {
+            panic!("Invalid value");
+        }
+        assert!(pat.match_str("/archive/latest").is_none());
+        assert!(pat.match_str("/archive//5").is_none());
+
+        assert!(matches!(
+            UrlPattern::parse("/<page>?/edit"),
+            Err(InvalidUrlPattern::InvalidOptionalPosition)
+        ));
+        assert!(matches!(
+            UrlPattern::parse("/<page>?/<rest..>"),
+            Err(InvalidUrlPattern::InvalidOptionalPosition)
+        ));
+    }
+
+    #[test]
+    fn decoded_captures() {
+        let pat: UrlPattern = "/users/<name>".try_into().unwrap();
+        let values = pat.match_str("/users/John%20Doe").unwrap();
+        if let [SegmentPatternValue::Wildcard(name)] = &values[..] {
+            assert_eq!(name.decoded(), Ok("John Doe"));
+            assert_eq!(name.raw(), "John%20Doe");
+        } else {
+            panic!("Invalid value");
+        }
+
+        let values = pat.match_str("/users/%FF").unwrap();
+        if let [SegmentPatternValue::Wildcard(name)] = &values[..] {
+            assert!(name.decoded().is_err());
+            assert_eq!(name.raw(), "%FF");
+        } else {
+            panic!("Invalid value");
+        }
+
+        // Constraints apply to the decoded value
+        let pat: UrlPattern = "/tags/<tag:[a-z ]+>".try_into().unwrap();
+        assert!(pat.match_str("/tags/two%20words").is_some());
+        assert!(pat.match_str("/tags/%FF").is_none());
+    }
+
+    #[test]
+    fn format_patterns() {
+        let pat: UrlPattern = "/files/<dir>/<name>.<ext:[a-z]+>".try_into().unwrap();
+        let url = pat
+            .format(&[("dir", &"my docs"), ("name", &"a/b"), ("ext", &"txt")])
+            .unwrap();
+        assert_eq!(url, "/files/my%20docs/a%2Fb.txt");
+        let values = pat.match_str(&url).unwrap();
+        if let [SegmentPatternValue::Wildcard(dir), SegmentPatternValue::Wildcard(name), _] =
+            &values[..]
+        {
+            assert_eq!(*dir, "my docs");
+            assert_eq!(*name, "a/b");
+        } else {
+            panic!("Invalid value");
+        }
+
+        assert_eq!(
+            pat.format(&[("dir", &"docs"), ("ext", &"txt")]),
+            Err(FormatError::MissingParameter("name".to_owned()))
+        );
+        assert_eq!(
+            pat.format(&[("dir", &"docs"), ("name", &"a"), ("ext", &"TXT")]),
+            Err(FormatError::InvalidParameter("ext".to_owned()))
+        );
+
+        let pat: UrlPattern = "/archive/<year>?/<month>?".try_into().unwrap();
+        assert_eq!(pat.format(&[]).unwrap(), "/archive");
+        assert_eq!(
+            pat.format(&[("month", &5)]),
+            Err(FormatError::MissingParameter("year".to_owned()))
+        );
+
+        let pat: UrlPattern = "/static/<path..>".try_into().unwrap();
+        assert_eq!(
+            pat.format(&[("path", &"css/main style.css")]).unwrap(),
+            "/static/css/main%20style.css"
+        );
+
+        let pat: UrlPattern = "/".try_into().unwrap();
+        assert_eq!(pat.format(&[]).unwrap(), "/");
+    }
+
     #[test]
     fn kleene_patterns() {
         let pat: UrlPattern = "/<capture..>".try_into().unwrap();
@@ -307,6 +1211,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn inner_kleene_patterns() {
+        let pat: UrlPattern = "/repos/<path..>/blob/<file>".try_into().unwrap();
+        assert_eq!(pat.to_string(), "/repos/<path..>/blob/<file>");
+        let values = pat.match_str("/repos/a/blob/b/blob/c").unwrap();
+        if let [SegmentPatternValue::WildcardKleene(path), SegmentPatternValue::Wildcard(file)] =
+            &values[..]
+        {
+            assert_eq!(*path, vec!["a", "blob", "b"]);
+            assert_eq!(*file, "c");
+        } else {
+            panic!("Invalid value");
+        }
+
+        let values = pat.match_str("/repos/blob/c").unwrap();
+        if let [SegmentPatternValue::WildcardKleene(path), SegmentPatternValue::Wildcard(file)] =
+            &values[..]
+        {
+            assert!(path.is_empty());
+            assert_eq!(*file, "c");
+        } else {
+            panic!("Invalid value");
+        }
+        assert!(pat.match_str("/repos/a/tree/c").is_none());
+        assert!(pat.match_str("/repos/c").is_none());
+        assert_eq!(
+            pat.format(&[("path", &"a/b"), ("file", &"c")]).unwrap(),
+            "/repos/a/b/blob/c"
+        );
+
+        assert!(matches!(
+            UrlPattern::parse("/<a..>/<b..>"),
+            Err(InvalidUrlPattern::InvalidKleenePosition)
+        ));
+        assert!(matches!(
+            UrlPattern::parse("/<a..>/<b>?"),
+            Err(InvalidUrlPattern::InvalidKleenePosition)
+        ));
+    }
+
     #[test]
     fn mixed_patterns() {
         let pat: UrlPattern = "/<capture..>".try_into().unwrap();
@@ -0,0 +1,247 @@
+use crate::tree::{rank, same_parts, same_shape};
+use crate::{match_parts, Capture, Constraint, SegmentPart, SegmentPattern, UrlPattern};
+
+/// How the paths matched by two url patterns relate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlap {
+    /// Both patterns match the same paths
+    Identical,
+    /// Every path matched by the other pattern is matched by this one
+    Covers,
+    /// Every path matched by this pattern is matched by the other one
+    CoveredBy,
+    /// Some paths may be matched by both patterns
+    Partial,
+}
+
+impl UrlPattern {
+    /// Compare the paths matched by the two patterns, `None` if no path matches both
+    ///
+    /// The analysis is conservative: captures with different constraints are assumed to
+    /// accept some common value, so `Partial` may be reported for disjoint patterns.
+    pub fn overlap(&self, other: &UrlPattern) -> Option<Overlap> {
+        // Requiring fewer query parameters matches more urls
+        let covers_query =
+            |a: &UrlPattern, b: &UrlPattern| a.query.iter().all(|k| b.query.contains(k));
+        match (
+            covers(&self.pattern, &other.pattern) && covers_query(self, other),
+            covers(&other.pattern, &self.pattern) && covers_query(other, self),
+        ) {
+            (true, true) => Some(Overlap::Identical),
+            (true, false) => Some(Overlap::Covers),
+            (false, true) => Some(Overlap::CoveredBy),
+            (false, false) if intersects(&self.pattern, &other.pattern) => Some(Overlap::Partial),
+            (false, false) => None,
+        }
+    }
+
+    /// Whether `later`, inserted after this pattern into a `PatternTree`, never gets a path
+    /// that this pattern does not get first
+    pub fn shadows(&self, later: &UrlPattern) -> bool {
+        matches!(
+            self.overlap(later),
+            Some(Overlap::Identical | Overlap::Covers)
+        ) && self.tried_before(later)
+    }
+
+    /// Whether `later`, inserted after this pattern into a `PatternTree`, gets every path
+    /// this pattern matches first
+    pub fn shadowed_by(&self, later: &UrlPattern) -> bool {
+        matches!(self.overlap(later), Some(Overlap::CoveredBy)) && !self.tried_before(later)
+    }
+
+    /// Whether a `PatternTree` tries this pattern before `later` on the paths both match
+    fn tried_before(&self, later: &UrlPattern) -> bool {
+        for (a, b) in self.pattern.iter().zip(&later.pattern) {
+            if !same_shape(a, b) {
+                // Children of equal rank are tried in insertion order
+                return rank(a) <= rank(b);
+            }
+            if is_kleene(a) && self.pattern.len() != later.pattern.len() {
+                // More segments after a shared kleene wildcard are tried first
+                return self.pattern.len() > later.pattern.len();
+            }
+        }
+        if self.pattern.len() == later.pattern.len() {
+            // Patterns ending at the same node are tried by their number of query parameters
+            return self.query.len() >= later.query.len();
+        }
+        // The values of a node are tried before its optional and kleene children
+        self.pattern.len() < later.pattern.len()
+    }
+}
+
+/// Values a segment accepts when present
+enum Single<'p> {
+    Fixed(&'p str),
+    Any,
+    Typed(&'p Constraint),
+    Compound(&'p [SegmentPart]),
+}
+
+/// `None` for kleene wildcards, which may span any number of segments
+fn single(segment: &SegmentPattern) -> Option<Single<'_>> {
+    match segment {
+        SegmentPattern::Fixed(s) => Some(Single::Fixed(s)),
+        SegmentPattern::Wildcard(_) | SegmentPattern::Optional(_, None) => Some(Single::Any),
+        SegmentPattern::TypedWildcard(_, constraint)
+        | SegmentPattern::Optional(_, Some(constraint)) => Some(Single::Typed(constraint)),
+        SegmentPattern::Compound(parts) => Some(Single::Compound(parts)),
+        SegmentPattern::WildcardKleene(_) => None,
+    }
+}
+
+fn is_optional(segment: &SegmentPattern) -> bool {
+    matches!(segment, SegmentPattern::Optional(_, _))
+}
+
+fn is_kleene(segment: &SegmentPattern) -> bool {
+    matches!(segment, SegmentPattern::WildcardKleene(_))
+}
+
+/// Whether the segments may match no path segment at all
+fn may_end(segments: &[SegmentPattern]) -> bool {
+    segments.iter().all(|s| is_optional(s) || is_kleene(s))
+}
+
+/// Whether every path matched by `b` is matched by `a`
+fn covers(a: &[SegmentPattern], b: &[SegmentPattern]) -> bool {
+    match (a.split_first(), b.split_first()) {
+        (None, _) => b.is_empty(),
+        (Some((a0, a_rest)), _) if is_kleene(a0) => {
+            (0..=b.len()).any(|taken| covers(a_rest, &b[taken..]))
+        }
+        (Some(_), None) => may_end(a),
+        (Some((a0, a_rest)), Some((b0, b_rest))) => {
+            // `b` may end where `a` may not
+            if is_kleene(b0) || (is_optional(b0) && !is_optional(a0)) {
+                return false;
+            }
+            match (single(a0), single(b0)) {
+                (Some(a0), Some(b0)) => covers_single(&a0, &b0) && covers(a_rest, b_rest),
+                _ => false,
+            }
+        }
+    }
+}
+
+fn covers_single(a: &Single, b: &Single) -> bool {
+    match (a, b) {
+        (Single::Any, _) => true,
+        (Single::Fixed(a), Single::Fixed(b)) => a == b,
+        (Single::Typed(a), Single::Typed(b)) => a == b,
+        (Single::Typed(constraint), Single::Fixed(s)) => {
+            Capture::new(s).satisfies(Some(constraint))
+        }
+        (Single::Compound(a), Single::Compound(b)) => same_parts(a, b),
+        (Single::Compound(parts), Single::Fixed(s)) => match_parts(parts, s, &mut Vec::new()),
+        _ => false,
+    }
+}
+
+/// Whether some path may be matched by both `a` and `b`
+fn intersects(a: &[SegmentPattern], b: &[SegmentPattern]) -> bool {
+    match (a.split_first(), b.split_first()) {
+        (None, None) => true,
+        (None, Some(_)) => may_end(b),
+        (Some(_), None) => may_end(a),
+        (Some((a0, _)), Some((b0, _))) if is_kleene(a0) && is_kleene(b0) => true,
+        (Some((a0, a_rest)), Some(_)) if is_kleene(a0) => {
+            (0..=b.len()).any(|taken| intersects(a_rest, &b[taken..]))
+        }
+        (Some(_), Some((b0, b_rest))) if is_kleene(b0) => {
+            (0..=a.len()).any(|taken| intersects(&a[taken..], b_rest))
+        }
+        (Some((a0, a_rest)), Some((b0, b_rest))) => {
+            let present = match (single(a0), single(b0)) {
+                (Some(a0), Some(b0)) => intersects_single(&a0, &b0) && intersects(a_rest, b_rest),
+                _ => unreachable!("kleene wildcards are handled above"),
+            };
+            present || (is_optional(a0) && may_end(b)) || (is_optional(b0) && may_end(a))
+        }
+    }
+}
+
+fn intersects_single(a: &Single, b: &Single) -> bool {
+    match (a, b) {
+        (Single::Any, _) | (_, Single::Any) => true,
+        (Single::Fixed(a), Single::Fixed(b)) => a == b,
+        (Single::Fixed(_), other) => covers_single(other, a),
+        (other, Single::Fixed(_)) => covers_single(other, b),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlap(a: &str, b: &str) -> Option<Overlap> {
+        let a = UrlPattern::parse(a).unwrap();
+        let b = UrlPattern::parse(b).unwrap();
+        a.overlap(&b)
+    }
+
+    fn shadows(earlier: &str, later: &str) -> bool {
+        let earlier = UrlPattern::parse(earlier).unwrap();
+        let later = UrlPattern::parse(later).unwrap();
+        earlier.shadows(&later) || earlier.shadowed_by(&later)
+    }
+
+    #[test]
+    fn overlapping_patterns() {
+        assert_eq!(
+            overlap("/users/<id>", "/users/<name>"),
+            Some(Overlap::Identical)
+        );
+        assert_eq!(overlap("/users/<id>", "/users/me"), Some(Overlap::Covers));
+        assert_eq!(overlap("/<rest..>", "/users/<id>"), Some(Overlap::Covers));
+        assert_eq!(
+            overlap("/users/<id:u32>", "/users/<id>"),
+            Some(Overlap::CoveredBy)
+        );
+        assert_eq!(
+            overlap("/users/<id:u32>", "/users/42"),
+            Some(Overlap::Covers)
+        );
+        assert_eq!(overlap("/users/<id:u32>", "/users/me"), None);
+        assert_eq!(overlap("/docs/<page>?", "/docs"), Some(Overlap::Covers));
+        assert_eq!(
+            overlap("/docs/<page>?", "/docs/<page>"),
+            Some(Overlap::Covers)
+        );
+        assert_eq!(overlap("/<a>/b", "/a/<b>"), Some(Overlap::Partial));
+        assert_eq!(overlap("/users/<id>", "/posts/<id>"), None);
+        assert_eq!(overlap("/users", "/users/<id>"), None);
+        assert_eq!(overlap("/search", "/search?q"), Some(Overlap::Covers));
+        assert_eq!(overlap("/search?q", "/search?lang"), Some(Overlap::Partial));
+        assert_eq!(
+            overlap("/repos/<path..>", "/repos/<path..>/blob/<file>"),
+            Some(Overlap::Covers)
+        );
+        assert_eq!(overlap("/repos/<path..>/blob/<file>", "/repos/a"), None);
+        assert_eq!(
+            overlap("/repos/<path..>/<file>", "/repos/a/blob/<file>"),
+            Some(Overlap::Covers)
+        );
+    }
+
+    #[test]
+    fn shadowed_patterns() {
+        // Only identical patterns shadow each other, more specific ones are tried first
+        assert!(shadows("/users/<id>", "/users/<name>"));
+        assert!(!shadows("/<rest..>", "/users/<id>"));
+        assert!(!shadows("/users/<id>", "/users/me"));
+        assert!(!shadows("/users/<id>", "/users/<id:u32>"));
+        assert!(!shadows("/docs/<page>?", "/docs"));
+        assert!(shadows("/files/<name>.<ext>", "/files/<stem>.<extension>"));
+        assert!(!shadows("/repos/<path..>", "/repos/<path..>/blob/<file>"));
+        assert!(!shadows(
+            "/repos/<path..>/<file>",
+            "/repos/<path..>/blob/<file>"
+        ));
+        assert!(shadows("/repos/<path..>/<file>", "/repos/<p..>/<f>"));
+        assert!(!shadows("/search", "/search?q"));
+        assert!(shadows("/search?q", "/search?q"));
+    }
+}
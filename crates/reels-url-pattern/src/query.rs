@@ -0,0 +1,258 @@
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use url::form_urlencoded;
+
+use crate::de::{deserialize_parsed, DeserializeError, ParseValue};
+
+/// Deserialize a query string, repeated keys and keys ending with "[]" collect their
+/// values into sequences
+///
+/// Examples
+/// ```
+/// # use reels_url_pattern::deserialize_query;
+/// #[derive(serde::Deserialize)]
+/// struct Search {
+///     q: String,
+///     tag: Vec<String>,
+/// }
+///
+/// let search: Search = deserialize_query("q=rust+web&tag=http&tag[]=wasm").unwrap();
+/// assert_eq!((search.q.as_str(), search.tag.len()), ("rust web", 2));
+/// ```
+pub fn deserialize_query<T: de::DeserializeOwned>(query: &str) -> Result<T, DeserializeError> {
+    let mut params: Vec<(String, Vec<String>)> = Vec::new();
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        let key = key.strip_suffix("[]").unwrap_or(&key);
+        match params.iter_mut().find(|(k, _)| k == key) {
+            Some((_, values)) => values.push(value.into_owned()),
+            None => params.push((key.to_owned(), vec![value.into_owned()])),
+        }
+    }
+    T::deserialize(QueryDeserializer { params: &params })
+}
+
+/// The parameters of a query string, each with all of its values
+struct QueryDeserializer<'p> {
+    params: &'p [(String, Vec<String>)],
+}
+
+impl<'de, 'p> de::Deserializer<'de> for QueryDeserializer<'p> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(Params {
+            params: self.params.iter(),
+            values: None,
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Access to the parameters as a map keyed by their names
+struct Params<'p, I: Iterator<Item = &'p (String, Vec<String>)>> {
+    params: I,
+    values: Option<&'p (String, Vec<String>)>,
+}
+
+impl<'de, 'p, I> MapAccess<'de> for Params<'p, I>
+where
+    I: Iterator<Item = &'p (String, Vec<String>)>,
+{
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let param = match self.params.next() {
+            Some(param) => param,
+            None => return Ok(None),
+        };
+        self.values = Some(param);
+        seed.deserialize(param.0.as_str().into_deserializer())
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (name, values) = self
+            .values
+            .take()
+            .ok_or_else(|| DeserializeError("Query parameter value without a name".to_owned()))?;
+        seed.deserialize(ValuesDeserializer { name, values })
+    }
+}
+
+/// The values of a query parameter, a sequence or a single value
+struct ValuesDeserializer<'p> {
+    name: &'p str,
+    values: &'p [String],
+}
+
+impl<'p> ParseValue<'p> for ValuesDeserializer<'p> {
+    fn describe(&self) -> String {
+        format!("query parameter `{}`", self.name)
+    }
+
+    fn value(&self) -> Result<&'p str, DeserializeError> {
+        match self.values {
+            [value] => Ok(value),
+            _ => Err(DeserializeError(format!(
+                "Query parameter `{}` is repeated but expects a single value",
+                self.name
+            ))),
+        }
+    }
+}
+
+impl<'de, 'p> de::Deserializer<'de> for ValuesDeserializer<'p> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.values {
+            [value] => visitor.visit_str(value),
+            _ => self.deserialize_seq(visitor),
+        }
+    }
+
+    deserialize_parsed!();
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.value()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let name = self.name;
+        visitor.visit_seq(Values {
+            values: self
+                .values
+                .chunks(1)
+                .map(move |values| ValuesDeserializer { name, values }),
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.value()?.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Access to the values of a repeated query parameter
+struct Values<I> {
+    values: I,
+}
+
+impl<'de, 'p, I> SeqAccess<'de> for Values<I>
+where
+    I: Iterator<Item = ValuesDeserializer<'p>>,
+{
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.values
+            .next()
+            .map(|value| seed.deserialize(value))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        q: String,
+        page: Option<u32>,
+        #[serde(default)]
+        tag: Vec<String>,
+    }
+
+    #[test]
+    fn repeated_keys_and_arrays() {
+        let search: Search = deserialize_query("q=rust+web&tag=http&tag[]=wasm").unwrap();
+        assert_eq!(
+            search,
+            Search {
+                q: "rust web".to_owned(),
+                page: None,
+                tag: vec!["http".to_owned(), "wasm".to_owned()],
+            }
+        );
+
+        let search: Search = deserialize_query("tag=http&page=2&q=").unwrap();
+        assert_eq!((search.q.as_str(), search.page), ("", Some(2)));
+        assert_eq!(search.tag, vec!["http"]);
+
+        let params: HashMap<String, Vec<u32>> = deserialize_query("id=1&id=2&other=3").unwrap();
+        assert_eq!(params["id"], vec![1, 2]);
+        assert_eq!(params["other"], vec![3]);
+    }
+
+    #[test]
+    fn invalid_queries() {
+        let error = deserialize_query::<Search>("q=a&q=b").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Query parameter `q` is repeated but expects a single value"
+        );
+
+        let error = deserialize_query::<Search>("q=a&page=last").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot parse query parameter `page` with value `last` as u32"
+        );
+
+        let error = deserialize_query::<Search>("page=1").unwrap_err();
+        assert_eq!(error.to_string(), "missing field `q`");
+    }
+}
@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    match_parts, query_keys, Capture, PathCapture, SegmentPart, SegmentPattern,
+    SegmentPatternValue, UrlPattern,
+};
+
+/// Segment trie of url patterns for matching a path against many patterns at once
+///
+/// At every segment the candidates are tried in a fixed precedence order regardless of
+/// insertion order: fixed segments first, then segments mixing literals and captures, typed
+/// wildcards, plain wildcards and finally kleene wildcards.
+/// Patterns of the same shape, like "/users/\<id\>" and "/users/\<name\>", end up on the
+/// same node and are tried in insertion order. Below a kleene wildcard the patterns with
+/// the most segments after it come first, so "/repos/\<path..\>/blob/\<file\>" is tried
+/// before "/repos/\<path..\>". Patterns with the same segments are tried by the number
+/// of query parameters they require, most first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternTree<T> {
+    root: Node<T>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node<T> {
+    /// Values of the patterns ending at this node along with their required query
+    /// parameters, the patterns requiring the most parameters first
+    values: Vec<(Vec<String>, T)>,
+    fixed: HashMap<String, Node<T>>,
+    /// Capturing children ordered by precedence
+    dynamic: Vec<(SegmentPattern, Node<T>)>,
+}
+
+impl<T> Default for PatternTree<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            values: Vec::new(),
+            fixed: HashMap::new(),
+            dynamic: Vec::new(),
+        }
+    }
+}
+
+impl<T> PatternTree<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, pattern: &UrlPattern, value: T) {
+        let mut node = &mut self.root;
+        for segment in &pattern.pattern {
+            node = node.child(segment);
+        }
+        // Stable for patterns requiring as many query parameters
+        let index = node
+            .values
+            .iter()
+            .position(|(query, _)| query.len() < pattern.query.len())
+            .unwrap_or(node.values.len());
+        node.values.insert(index, (pattern.query.clone(), value));
+    }
+
+    /// Call `f` with every value whose pattern matches the url path, in order of
+    /// precedence, until it returns `Some`
+    pub fn find_url<'a, 't, R, F>(&'t self, url: &'a Url, f: F) -> Option<R>
+    where
+        F: FnMut(&'t T, PathCapture<'a>) -> Option<R>,
+    {
+        let segments: Vec<&str> = url.path_segments()?.collect();
+        self.find(&segments, url.query(), f)
+    }
+
+    /// Same as `find_url` for a path starting with "/", optionally followed by a query
+    pub fn find_str<'a, 't, R, F>(&'t self, path: &'a str, f: F) -> Option<R>
+    where
+        F: FnMut(&'t T, PathCapture<'a>) -> Option<R>,
+    {
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path, None),
+        };
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        self.find(&segments, query, f)
+    }
+
+    /// Values of every pattern matching the url path in order of precedence
+    pub fn matches_url(&self, url: &Url) -> Vec<&T> {
+        let mut values = Vec::new();
+        self.find_url(url, |value, _| {
+            values.push(value);
+            None::<()>
+        });
+        values
+    }
+
+    /// Every value in order of precedence, fixed segments sorted by their value
+    pub fn values(&self) -> Vec<&T> {
+        let mut values = Vec::new();
+        self.root.collect(&mut values);
+        values
+    }
+
+    fn find<'a, 't, R, F>(
+        &'t self,
+        segments: &[&'a str],
+        query: Option<&str>,
+        mut f: F,
+    ) -> Option<R>
+    where
+        F: FnMut(&'t T, PathCapture<'a>) -> Option<R>,
+    {
+        let query = query_keys(query);
+        let mut f = |(required, value): &'t (Vec<String>, T), captures| {
+            let present = required
+                .iter()
+                .all(|key| query.iter().any(|k| k == key.as_str()));
+            if present {
+                f(value, captures)
+            } else {
+                None
+            }
+        };
+        let mut captures = Vec::new();
+        self.root.find(segments, &mut captures, &mut f)
+    }
+}
+
+impl<T> Node<T> {
+    fn collect<'t>(&'t self, values: &mut Vec<&'t T>) {
+        values.extend(self.values.iter().map(|(_, value)| value));
+        let mut fixed: Vec<_> = self.fixed.iter().collect();
+        fixed.sort_by_key(|(segment, _)| *segment);
+        for (_, node) in fixed {
+            node.collect(values);
+        }
+        for (_, node) in &self.dynamic {
+            node.collect(values);
+        }
+    }
+
+    fn child(&mut self, segment: &SegmentPattern) -> &mut Node<T> {
+        if let SegmentPattern::Fixed(s) = segment {
+            return self.fixed.entry(s.clone()).or_default();
+        }
+        let index = match self
+            .dynamic
+            .iter()
+            .position(|(pattern, _)| same_shape(pattern, segment))
+        {
+            Some(index) => index,
+            None => {
+                // Keep the children sorted by precedence, stable for equal ranks
+                let index = self
+                    .dynamic
+                    .iter()
+                    .position(|(pattern, _)| rank(pattern) > rank(segment))
+                    .unwrap_or(self.dynamic.len());
+                self.dynamic
+                    .insert(index, (segment.clone(), Node::default()));
+                index
+            }
+        };
+        &mut self.dynamic[index].1
+    }
+
+    fn find<'a, 't, R, F>(
+        &'t self,
+        segments: &[&'a str],
+        captures: &mut Vec<SegmentPatternValue<'a>>,
+        f: &mut F,
+    ) -> Option<R>
+    where
+        F: FnMut(&'t (Vec<String>, T), PathCapture<'a>) -> Option<R>,
+    {
+        if let Some((segment, rest)) = segments.split_first() {
+            if let Some(child) = self.fixed.get(*segment) {
+                if let Some(found) = child.find(rest, captures, f) {
+                    return Some(found);
+                }
+            }
+        } else if let Some(found) = self.values.iter().find_map(|v| f(v, captures.clone())) {
+            return Some(found);
+        }
+
+        for (pattern, child) in &self.dynamic {
+            let found = match pattern {
+                SegmentPattern::Fixed(_) => unreachable!("fixed segments are not dynamic"),
+                SegmentPattern::Wildcard(_) | SegmentPattern::TypedWildcard(_, _) => {
+                    let (segment, rest) = match segments.split_first() {
+                        Some(split) => split,
+                        None => continue,
+                    };
+                    let capture = Capture::new(segment);
+                    if let SegmentPattern::TypedWildcard(_, constraint) = pattern {
+                        if !capture.satisfies(Some(constraint)) {
+                            continue;
+                        }
+                    }
+                    captures.push(SegmentPatternValue::Wildcard(capture));
+                    let found = child.find(rest, captures, f);
+                    captures.pop();
+                    found
+                }
+                SegmentPattern::Compound(parts) => {
+                    let (segment, rest) = match segments.split_first() {
+                        Some(split) => split,
+                        None => continue,
+                    };
+                    let len = captures.len();
+                    if !match_parts(parts, segment, captures) {
+                        continue;
+                    }
+                    let found = child.find(rest, captures, f);
+                    captures.truncate(len);
+                    found
+                }
+                SegmentPattern::Optional(_, constraint) => {
+                    let (value, rest) = match segments.split_first() {
+                        Some((segment, rest)) if !segment.is_empty() => {
+                            let capture = Capture::new(segment);
+                            if !capture.satisfies(constraint.as_ref()) {
+                                continue;
+                            }
+                            (SegmentPatternValue::Wildcard(capture), rest)
+                        }
+                        // Once missing, the following optional captures are missing too
+                        Some((_, rest)) if rest.iter().any(|segment| !segment.is_empty()) => {
+                            continue
+                        }
+                        Some((_, rest)) => (SegmentPatternValue::Missing, rest),
+                        None => (SegmentPatternValue::Missing, segments),
+                    };
+                    captures.push(value);
+                    let found = child.find(rest, captures, f);
+                    captures.pop();
+                    found
+                }
+                SegmentPattern::WildcardKleene(_) => {
+                    // Taking the fewest segments first tries the patterns with the most
+                    // segments after the kleene wildcard first, the ones ending with it last
+                    (0..=segments.len()).find_map(|taken| {
+                        let (values, rest) = segments.split_at(taken);
+                        let values = values.iter().map(|segment| Capture::new(segment));
+                        captures.push(SegmentPatternValue::WildcardKleene(values.collect()));
+                        let found = child.find(rest, captures, f);
+                        captures.pop();
+                        found
+                    })
+                }
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+/// Precedence of a capturing segment, lower ranks are tried first
+pub(crate) fn rank(pattern: &SegmentPattern) -> u8 {
+    match pattern {
+        SegmentPattern::Fixed(_) => 0,
+        SegmentPattern::Compound(_) => 1,
+        SegmentPattern::TypedWildcard(_, _) => 2,
+        SegmentPattern::Wildcard(_) => 3,
+        SegmentPattern::Optional(_, _) => 4,
+        SegmentPattern::WildcardKleene(_) => 5,
+    }
+}
+
+/// Whether two segments match exactly the same values, capture names aside
+pub(crate) fn same_shape(a: &SegmentPattern, b: &SegmentPattern) -> bool {
+    match (a, b) {
+        (SegmentPattern::Fixed(a), SegmentPattern::Fixed(b)) => a == b,
+        (SegmentPattern::Wildcard(_), SegmentPattern::Wildcard(_)) => true,
+        (SegmentPattern::TypedWildcard(_, a), SegmentPattern::TypedWildcard(_, b)) => a == b,
+        (SegmentPattern::Compound(a), SegmentPattern::Compound(b)) => same_parts(a, b),
+        (SegmentPattern::Optional(_, a), SegmentPattern::Optional(_, b)) => a == b,
+        (SegmentPattern::WildcardKleene(_), SegmentPattern::WildcardKleene(_)) => true,
+        _ => false,
+    }
+}
+
+/// Whether two compound segments match exactly the same values, capture names aside
+pub(crate) fn same_parts(a: &[SegmentPart], b: &[SegmentPart]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|parts| match parts {
+            (SegmentPart::Literal(a), SegmentPart::Literal(b)) => a == b,
+            (SegmentPart::Capture(_, a), SegmentPart::Capture(_, b)) => a == b,
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(patterns: &[&'static str]) -> PatternTree<&'static str> {
+        let mut tree = PatternTree::new();
+        for pattern in patterns {
+            tree.insert(&UrlPattern::parse(pattern).unwrap(), *pattern);
+        }
+        tree
+    }
+
+    fn all(tree: &PatternTree<&'static str>, path: &str) -> Vec<&'static str> {
+        let mut values = Vec::new();
+        tree.find_str(path, |value, _| {
+            values.push(*value);
+            None::<()>
+        });
+        values
+    }
+
+    #[test]
+    fn precedence_ignores_insertion_order() {
+        let tree = tree(&["/<rest..>", "/users/<name>", "/users/<id>", "/users/me"]);
+        assert_eq!(
+            all(&tree, "/users/me"),
+            vec!["/users/me", "/users/<name>", "/users/<id>", "/<rest..>"]
+        );
+        assert_eq!(
+            all(&tree, "/users/42"),
+            vec!["/users/<name>", "/users/<id>", "/<rest..>"]
+        );
+        assert_eq!(all(&tree, "/users"), vec!["/<rest..>"]);
+    }
+
+    #[test]
+    fn typed_wildcards_come_before_plain_ones() {
+        let tree = tree(&["/users/<name>", "/users/<id:u32>", "/users/<uid:uuid>"]);
+        assert_eq!(
+            all(&tree, "/users/42"),
+            vec!["/users/<id:u32>", "/users/<name>"]
+        );
+        assert_eq!(all(&tree, "/users/alice"), vec!["/users/<name>"]);
+    }
+
+    #[test]
+    fn compound_segments_come_before_wildcards() {
+        let tree = tree(&["/files/<file>", "/files/<name>.<ext>", "/files/<stem>.json"]);
+        assert_eq!(
+            all(&tree, "/files/a.json"),
+            vec!["/files/<name>.<ext>", "/files/<stem>.json", "/files/<file>"]
+        );
+        assert_eq!(all(&tree, "/files/a"), vec!["/files/<file>"]);
+    }
+
+    #[test]
+    fn optional_segments() {
+        let docs = tree(&["/docs/<page>?", "/docs/<section>/<page>?"]);
+        assert_eq!(all(&docs, "/docs"), vec!["/docs/<page>?"]);
+        assert_eq!(
+            all(&docs, "/docs/intro"),
+            vec!["/docs/<section>/<page>?", "/docs/<page>?"]
+        );
+        assert_eq!(
+            all(&docs, "/docs/intro/setup"),
+            vec!["/docs/<section>/<page>?"]
+        );
+
+        let archive = tree(&["/archive/<year>?/<month>?"]);
+        assert_eq!(
+            all(&archive, "/archive//"),
+            vec!["/archive/<year>?/<month>?"]
+        );
+        assert!(all(&archive, "/archive//5").is_empty());
+    }
+
+    #[test]
+    fn captures_follow_the_matched_branch() {
+        let tree = tree(&["/a/<x>/c", "/a/<y>/<z>"]);
+        let captures = tree
+            .find_str("/a/b/d", |value, captures| Some((*value, captures)))
+            .unwrap();
+        assert_eq!(captures.0, "/a/<y>/<z>");
+        if let [SegmentPatternValue::Wildcard(y), SegmentPatternValue::Wildcard(z)] =
+            &captures.1[..]
+        {
+            assert_eq!((y.raw(), z.raw()), ("b", "d"));
+        } else {
+            panic!("Invalid value");
+        }
+    }
+
+    #[test]
+    fn values_in_precedence_order() {
+        let tree = tree(&["/<rest..>", "/b/<id>", "/a", "/b/<id:u32>", "/"]);
+        let values: Vec<&str> = tree.values().into_iter().copied().collect();
+        assert_eq!(
+            values,
+            vec!["/", "/a", "/b/<id:u32>", "/b/<id>", "/<rest..>"]
+        );
+    }
+
+    #[test]
+    fn inner_kleene() {
+        let repos = tree(&[
+            "/repos/<path..>",
+            "/repos/<path..>/blob/<file>",
+            "/repos/<path..>/<action>/<file>",
+        ]);
+        assert_eq!(
+            all(&repos, "/repos/a/b/blob/c"),
+            vec![
+                "/repos/<path..>/blob/<file>",
+                "/repos/<path..>/<action>/<file>",
+                "/repos/<path..>"
+            ]
+        );
+        assert_eq!(
+            all(&repos, "/repos/a/b"),
+            vec!["/repos/<path..>/<action>/<file>", "/repos/<path..>"]
+        );
+
+        let captured = repos.find_str("/repos/a/blob/b/blob/c", |_, captures| Some(captures));
+        if let Some(
+            [SegmentPatternValue::WildcardKleene(path), SegmentPatternValue::Wildcard(file)],
+        ) = captured.as_deref()
+        {
+            assert_eq!(*path, vec!["a", "blob", "b"]);
+            assert_eq!(*file, "c");
+        } else {
+            panic!("Invalid value");
+        }
+    }
+
+    #[test]
+    fn query_parameters() {
+        let search = tree(&["/search", "/search?q", "/search?q&lang"]);
+        assert_eq!(
+            all(&search, "/search?lang=en&q=rust"),
+            vec!["/search?q&lang", "/search?q", "/search"]
+        );
+        assert_eq!(all(&search, "/search?q=rust"), vec!["/search?q", "/search"]);
+        assert_eq!(all(&search, "/search"), vec!["/search"]);
+    }
+
+    #[test]
+    fn root_and_kleene() {
+        let tree = tree(&["/", "/a/<rest..>"]);
+        assert_eq!(all(&tree, "/"), vec!["/"]);
+        assert_eq!(all(&tree, "/a"), vec!["/a/<rest..>"]);
+        assert_eq!(all(&tree, "/a/b/c"), vec!["/a/<rest..>"]);
+        assert!(all(&tree, "/b").is_empty());
+    }
+}
@@ -1,24 +1,20 @@
 use reels::{
+    extract::{Headers, Path, Query},
     get,
     http::{HttpResponse, StatusCode},
-    router::{Router, SegmentPatternValue},
+    router::{Router, SegmentPatternValue, TrailingSlash},
     server::Server,
 };
+use serde::Deserialize;
 use std::error::Error;
 
 #[get("/")]
-fn index() -> HttpResponse {
-    HttpResponse::builder()
-        .header(
-            "content-type".to_owned(),
-            "text/html; charset=utf-8".to_owned(),
-        )
-        .body("Hello world!".to_owned())
-        .finalize()
+fn index() -> &'static str {
+    "Hello world!"
 }
 
-/// Match on "/users/<uid>" where uid could be parsed into a u32
-#[get("/users/<uid>")]
+/// Match on "/users/<uid>" where uid is a u32, other values go on to "/users/<name>"
+#[get("/users/<uid:u32>")]
 fn user_uid(uid: u32) -> HttpResponse {
     HttpResponse::builder()
         .header(
@@ -30,7 +26,7 @@ fn user_uid(uid: u32) -> HttpResponse {
 }
 
 /// Match on "/users/<name>"
-#[get("/users/<name>")]
+#[get("/users/<name>", name = "user")]
 fn user(name: &str) -> HttpResponse {
     HttpResponse::builder()
         .header(
@@ -41,7 +37,54 @@ fn user(name: &str) -> HttpResponse {
         .finalize()
 }
 
-/// Match on any path and store the url segments into the segments argument
+/// Handler arguments that are not path captures are extracted from the request
+#[get("/agent")]
+fn agent(headers: Headers) -> String {
+    format!(
+        "Your user agent is {}",
+        headers.get("user-agent").unwrap_or("unknown")
+    )
+}
+
+#[derive(Deserialize)]
+struct Post {
+    user: String,
+    id: u32,
+}
+
+/// Captures are deserialized into the fields of the same name
+#[get("/users/<user>/posts/<id:u32>")]
+fn post(Path(post): Path<Post>) -> String {
+    format!("Post {} of {}", post.id, post.user)
+}
+
+/// Arguments are matched with the captures by name, not by position
+#[get("/compare/<base>/<head>")]
+fn compare(head: &str, base: &str) -> String {
+    format!("Comparing {} with {}", head, base)
+}
+
+#[derive(Deserialize)]
+struct Search {
+    q: String,
+    #[serde(default)]
+    tag: Vec<String>,
+}
+
+/// Only match when the query has a "q" parameter, like "/search?q=reels&tag=web&tag=wasm"
+#[get("/search?q")]
+fn search(Query(search): Query<Search>) -> String {
+    format!("Searching {} tagged {:?}", search.q, search.tag)
+}
+
+/// Match on both "/docs" and "/docs/<page>"
+#[get("/docs/<page>?")]
+fn docs(page: Option<&str>) -> String {
+    format!("Docs page: {}", page.unwrap_or("index"))
+}
+
+/// Handle requests that match no route, the url segments are stored into the segments
+/// argument
 #[get("/<segments..>")]
 fn fallback(segments: Vec<&str>) -> HttpResponse {
     HttpResponse::builder()
@@ -62,12 +105,20 @@ fn fallback(segments: Vec<&str>) -> HttpResponse {
 
 fn main() -> Result<(), Box<dyn Error>> {
     let router = Router::new()
+        .trailing_slash(TrailingSlash::Redirect)
         .mount(user_uid)?
         .mount(user)?
         .mount(index)?
-        .mount(fallback)?;
+        .mount(agent)?
+        .mount(post)?
+        .mount(compare)?
+        .mount(search)?
+        .mount(docs)?
+        .fallback(fallback)?;
+    let greeting = router.url_for("user", &[("name", &"reels")])?;
     let server = Server::new(router).bind("127.0.0.1:8080")?;
     println!("Listening on http://127.0.0.1:8080");
+    println!("Say hi at http://127.0.0.1:8080{}", greeting);
     server.start();
     Ok(())
 }
@@ -0,0 +1,50 @@
+use lunatic::{Mailbox, Process};
+use reels::{extract::State, get, register_state, router::Router, server::Server};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// State shared with every connection process
+#[derive(Serialize, Deserialize, Clone)]
+struct AppState {
+    name: String,
+    /// Handle to the singleton process that counts visits
+    counter: Process<String>,
+}
+
+register_state!(AppState);
+
+#[get("/")]
+fn index(State(state): State<AppState>) -> String {
+    state.counter.send("/".to_owned());
+    format!("Welcome to {}!", state.name)
+}
+
+#[get("/about")]
+fn about(State(state): State<AppState>) -> String {
+    state.counter.send("/about".to_owned());
+    format!("{} is served by reels", state.name)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let counter = Process::spawn((), |_, mailbox: Mailbox<String>| {
+        let mut visits = 0;
+        loop {
+            let path = mailbox.receive();
+            visits += 1;
+            println!("Visit #{} to {}", visits, path);
+        }
+    });
+    let state = AppState {
+        name: "Reels".to_owned(),
+        counter,
+    };
+
+    let router = Router::new()
+        .with_state(state)?
+        .mount(index)?
+        .mount(about)?;
+    let server = Server::new(router).bind("127.0.0.1:8080")?;
+    println!("Listening on http://127.0.0.1:8080");
+    server.start();
+    Ok(())
+}
@@ -55,14 +55,17 @@ fn render_dir(path: &Path) -> HttpResponse {
         .finalize()
 }
 
+/// Stream the file to the client instead of loading it into memory
 fn render_file(path: &Path) -> HttpResponse {
+    let file = fs::File::open(path).expect("unable to open file");
+    let length = file.metadata().expect("unable to read file metadata").len();
     HttpResponse::builder()
         .status(StatusCode::OK)
         .header(
             "content-type".to_owned(),
             "application/force-download".to_owned(),
         )
-        .body_bytes(fs::read(path).expect("unable to read file"))
+        .body_reader(file, Some(length))
         .finalize()
 }
 